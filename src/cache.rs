@@ -0,0 +1,168 @@
+use crate::engine::{cache_dir, Build, Engine};
+use crate::error::Error;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+pub struct CacheEntry {
+    engine: Engine,
+    size: u64,
+    last_used: Option<SystemTime>,
+    complete: bool,
+}
+
+impl CacheEntry {
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn last_used(&self) -> Option<SystemTime> {
+        self.last_used
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+}
+
+/// The engines downloaded to `dirs::cache_dir()/flutter-engine/<version>/<target>/<build>`.
+pub struct EngineCache {
+    root: PathBuf,
+}
+
+impl EngineCache {
+    pub fn new() -> Self {
+        Self { root: cache_dir() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn entries(&self) -> Result<Vec<CacheEntry>, Error> {
+        let mut entries = Vec::new();
+        for version in sub_dirs(&self.root)? {
+            for target in sub_dirs(&self.root.join(&version))? {
+                for build in sub_dirs(&self.root.join(&version).join(&target))? {
+                    // Skips staging directories of interrupted downloads.
+                    let build = match build.parse::<Build>() {
+                        Ok(build) => build,
+                        Err(_) => continue,
+                    };
+                    let engine = Engine::new(version.clone(), target.clone(), build);
+                    entries.push(CacheEntry {
+                        size: dir_size(&engine.engine_dir()),
+                        last_used: engine.last_used(),
+                        complete: engine.is_complete(),
+                        engine,
+                    });
+                }
+            }
+        }
+        entries.sort_by_key(|entry| {
+            let engine = &entry.engine;
+            (
+                engine.version().to_string(),
+                engine.target().to_string(),
+                engine.build().build().to_string(),
+            )
+        });
+        Ok(entries)
+    }
+
+    /// Removes an engine including any leftovers of an interrupted download.
+    pub fn remove(&self, engine: &Engine) -> Result<(), Error> {
+        let dir = engine.engine_dir();
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+        for ext in &["zip.part", "staging"] {
+            let path = dir.with_extension(ext);
+            if path.is_dir() {
+                std::fs::remove_dir_all(&path)?;
+            } else if path.exists() {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        // Prunes the target and version dirs once they are empty.
+        let target_dir = dir.parent().unwrap();
+        if std::fs::remove_dir(target_dir).is_ok() {
+            std::fs::remove_dir(target_dir.parent().unwrap()).ok();
+        }
+        Ok(())
+    }
+
+    /// Removes all versions that no build used within `max_age`, as well as
+    /// incomplete downloads. Returns the removed versions.
+    pub fn gc(&self, max_age: Duration) -> Result<Vec<String>, Error> {
+        let now = SystemTime::now();
+        let entries = self.entries()?;
+        let recent: HashSet<&str> = entries
+            .iter()
+            .filter(|entry| entry.complete)
+            .filter(|entry| match entry.last_used {
+                Some(last_used) => now
+                    .duration_since(last_used)
+                    .map(|age| age <= max_age)
+                    .unwrap_or(true),
+                None => false,
+            })
+            .map(|entry| entry.engine.version())
+            .collect();
+
+        let mut removed = Vec::new();
+        for version in sub_dirs(&self.root)? {
+            if !recent.contains(version.as_str()) {
+                std::fs::remove_dir_all(self.root.join(&version))?;
+                removed.push(version);
+            }
+        }
+        for entry in &entries {
+            if !entry.complete && recent.contains(entry.engine.version()) {
+                self.remove(&entry.engine)?;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+impl Default for EngineCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn sub_dirs(dir: &Path) -> Result<Vec<String>, Error> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut dirs = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            if let Some(name) = entry.file_name().to_str() {
+                dirs.push(name.to_string());
+            }
+        }
+    }
+    Ok(dirs)
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| match entry.metadata() {
+                    Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+                    Ok(metadata) => metadata.len(),
+                    Err(_) => 0,
+                })
+                .sum()
+        })
+        .unwrap_or(0)
+}
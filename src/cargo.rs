@@ -1,63 +1,175 @@
+use crate::cargo_args::CargoArgs;
+use crate::engine::Build;
 use crate::error::Error;
+use crate::subprocess;
 use cargo::core::{Package, Workspace};
 use cargo::util::important_paths::find_root_manifest_for_wd;
 use cargo::util::Config;
-use std::path::PathBuf;
-use std::process::Command;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+/// Executables and cdylibs of a workspace member built by cargo.
+#[derive(Clone, Debug, Default)]
+pub struct Artifacts {
+    pub bins: Vec<PathBuf>,
+    pub libs: Vec<PathBuf>,
+}
+
+/// A `compiler-artifact` message of `--message-format=json`.
+#[derive(Deserialize)]
+struct Message {
+    reason: String,
+    manifest_path: Option<PathBuf>,
+    target: Option<MessageTarget>,
+    #[serde(default)]
+    filenames: Vec<PathBuf>,
+    executable: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct MessageTarget {
+    name: String,
+    kind: Vec<String>,
+}
 
 pub struct Cargo<'a> {
-    args: Vec<&'a str>,
+    args: CargoArgs<'a>,
+    mode: Option<Build>,
+    /// `true` if the profile given with `--profile` inherits from release.
+    release_profile: bool,
     workspace: Workspace<'a>,
 }
 
 impl<'a> Cargo<'a> {
-    pub fn new(config: &'a mut Config, args: Vec<&'a str>) -> Result<Self, Error> {
+    /// Creates a new cargo context. `mode` overrides the flutter build mode
+    /// that is otherwise derived from `--release`.
+    pub fn new(
+        config: &'a mut Config,
+        args: Vec<&'a str>,
+        offline: bool,
+        mode: Option<Build>,
+    ) -> Result<Self, Error> {
+        let args = CargoArgs::parse(&args);
         let root_manifest = find_root_manifest_for_wd(config.cwd())?;
-        let target_dir = root_manifest
-            .parent()
-            .unwrap()
-            .join("target")
-            .join("flutter");
+        let release_profile = args
+            .profile
+            .map(|profile| {
+                let manifest = std::fs::read_to_string(&root_manifest).unwrap_or_default();
+                inherits_release(&manifest, profile)
+            })
+            .unwrap_or_default();
+        // Resolved like cargo does: `--target-dir`, `CARGO_TARGET_DIR`,
+        // `build.target-dir` and `<workspace>/target`.
+        let target_dir = match args.target_dir {
+            Some(dir) => config.cwd().join(dir),
+            None => match config.target_dir()? {
+                Some(dir) => dir.into_path_unlocked(),
+                None => root_manifest.parent().unwrap().join("target"),
+            },
+        };
+        let mut target_dir = target_dir.join("flutter");
+        // Profile builds link against the profile engine, which must not
+        // overwrite the release engine in `deps`.
+        if mode == Some(Build::Profile) {
+            target_dir = target_dir.join("profile");
+        }
         config
-            .configure(0, None, &None, false, false, false, &Some(target_dir), &[])
+            .configure(
+                0,
+                None,
+                &None,
+                false,
+                false,
+                offline,
+                &Some(target_dir),
+                &[],
+            )
             .unwrap();
 
         let workspace = Workspace::new(&root_manifest, config)?;
-        Ok(Self { args, workspace })
+        Ok(Self {
+            args,
+            mode,
+            release_profile,
+            workspace,
+        })
     }
 
-    fn arg<F: Fn(&str) -> bool>(&self, matches: F) -> Option<&str> {
-        self.args
-            .iter()
-            .position(|f| matches(f))
-            .map(|pos| self.args.get(pos + 1))
-            .unwrap_or_default()
-            .cloned()
+    pub fn args(&self) -> &CargoArgs<'a> {
+        &self.args
     }
 
     pub fn cmd(&self) -> &str {
-        self.args.iter().next().expect("Expected command")
+        self.args.command.expect("Expected command")
     }
 
     pub fn target(&self) -> Option<&str> {
-        self.arg(|f| f == "--target")
+        self.args.target
     }
 
     pub fn package(&self) -> Result<&Package, Error> {
-        Ok(
-            if let Some(package) = self.arg(|f| f == "--package" || f == "-p") {
-                self.workspace()
+        Ok(if let Some(package) = self.args.packages.first() {
+            self.workspace()
+                .members()
+                .find(|pkg| pkg.name().as_str() == *package)
+                .ok_or(Error::PackageNotMember)?
+        } else {
+            self.workspace().current()?
+        })
+    }
+
+    /// The packages selected with `--workspace` or `-p`, defaulting to the
+    /// current package or the default members of a virtual workspace.
+    pub fn packages(&self) -> Result<Vec<&Package>, Error> {
+        if self.args.workspace {
+            return Ok(self
+                .workspace
+                .members()
+                .filter(|pkg| !self.args.exclude.contains(&pkg.name().as_str()))
+                .collect());
+        }
+        if self.args.packages.is_empty() {
+            return Ok(match self.workspace.current_opt() {
+                Some(package) => vec![package],
+                None => self.workspace.default_members().collect(),
+            });
+        }
+        self.args
+            .packages
+            .iter()
+            .map(|package| {
+                self.workspace
                     .members()
-                    .find(|pkg| pkg.name().as_str() == package)
-                    .ok_or(Error::PackageNotMember)?
-            } else {
-                self.workspace().current()?
-            },
-        )
+                    .find(|pkg| pkg.name().as_str() == *package)
+                    .ok_or(Error::PackageNotMember)
+            })
+            .collect()
     }
 
+    /// Returns `true` if the rust code is built with optimizations, which
+    /// is the case for flutter release and profile builds.
     pub fn release(&self) -> bool {
-        self.args.iter().any(|f| *f == "--release")
+        match self.mode {
+            Some(Build::Release) | Some(Build::Profile) => true,
+            _ => self.args.release || self.release_profile,
+        }
+    }
+
+    pub fn build(&self) -> Build {
+        match self.mode {
+            Some(mode) => mode,
+            None if self.release() => Build::Release,
+            None => Build::Debug,
+        }
+    }
+
+    /// Returns `true` if `--offline` was passed or `net.offline` is set in
+    /// the cargo config.
+    pub fn offline(&self) -> bool {
+        self.workspace.config().offline() || self.args.offline
     }
 
     pub fn host_triple(&self) -> Result<String, Error> {
@@ -91,32 +203,201 @@ impl<'a> Cargo<'a> {
         } else {
             flutter_dir
         };
-        if self.release() {
-            triple_dir.join("release")
-        } else {
-            triple_dir.join("debug")
+        triple_dir.join(self.profile_dir())
+    }
+
+    /// Name of the directory cargo puts the artifacts of the profile in.
+    fn profile_dir(&self) -> &str {
+        match self.args.profile {
+            Some("dev") | Some("test") => "debug",
+            Some("bench") => "release",
+            Some(profile) => profile,
+            None if self.release() => "release",
+            None => "debug",
         }
     }
 
     fn cargo_command(&self) -> Command {
+        self.command(self.args.cargo_args(), &self.args.app_args)
+    }
+
+    fn command(&self, args: &[&str], app_args: &[&str]) -> Command {
+        // The user's target dir is replaced by its flutter subdirectory
         let mut cmd = Command::new("cargo");
         cmd.current_dir(self.workspace.config().cwd())
-            .args(&self.args)
+            .args(args)
             .arg("--target-dir")
             .arg(self.target_dir());
+        // Cargo rejects `--release` together with `--profile`
+        if self.release() && !self.args.release && self.args.profile.is_none() {
+            cmd.arg("--release");
+        }
+        if self.offline() && !self.args.offline {
+            cmd.arg("--offline");
+        }
+        if !app_args.is_empty() {
+            cmd.arg("--").args(app_args);
+        }
         cmd
     }
 
     pub fn exec(&self) -> Result<(), Error> {
-        let status = self.cargo_command().status().expect("Success");
-        if status.code() != Some(0) {
-            return Err(Error::CargoError);
+        let step = format!("cargo {}", self.cmd());
+        subprocess::run_attached(&step, &mut self.cargo_command())
+    }
+
+    /// Runs the build and returns what it produced by package name. Only
+    /// the targets selected with `--bin` or `--example` are returned as
+    /// executables.
+    pub fn exec_artifacts(&self) -> Result<HashMap<String, Artifacts>, Error> {
+        let mut args = self.args.cargo_args().to_vec();
+        // Diagnostics are still rendered to stderr
+        args.push("--message-format=json-render-diagnostics");
+        let mut cmd = self.command(&args, &self.args.app_args);
+        cmd.stdout(Stdio::piped());
+        let step = format!("cargo {}", self.cmd());
+        let mut child = cmd
+            .spawn()
+            .map_err(|err| subprocess::spawn_failed(&step, &cmd, err))?;
+        let members: HashMap<&Path, &str> = self
+            .workspace
+            .members()
+            .map(|member| (member.manifest_path(), member.name().as_str()))
+            .collect();
+        let mut artifacts: HashMap<String, Artifacts> = HashMap::new();
+        for line in BufReader::new(child.stdout.take().unwrap()).lines() {
+            let line = line?;
+            let message: Message = match serde_json::from_str(&line) {
+                Ok(message) => message,
+                Err(_) => {
+                    println!("{}", line);
+                    continue;
+                }
+            };
+            let member = message
+                .manifest_path
+                .as_ref()
+                .and_then(|path| members.get(path.as_path()));
+            let (target, member) = match (message.target, member) {
+                (Some(target), Some(member)) if message.reason == "compiler-artifact" => {
+                    (target, member)
+                }
+                _ => continue,
+            };
+            let artifacts = artifacts.entry(member.to_string()).or_default();
+            if let Some(executable) = message.executable {
+                if self.selected(&target) {
+                    artifacts.bins.push(executable);
+                }
+            } else if target.kind.iter().any(|kind| kind == "cdylib") {
+                artifacts
+                    .libs
+                    .extend(message.filenames.into_iter().filter(|path| {
+                        match path.extension().and_then(|ext| ext.to_str()) {
+                            Some(ext) => ["so", "dylib", "dll"].contains(&ext),
+                            None => false,
+                        }
+                    }));
+            }
         }
-        Ok(())
+        let status = child.wait()?;
+        subprocess::check(&step, &cmd, status, Vec::new())?;
+        Ok(artifacts)
+    }
+
+    fn selected(&self, target: &MessageTarget) -> bool {
+        let is = |kind: &str, name: Option<&str>| {
+            name == Some(target.name.as_str()) && target.kind.iter().any(|k| k == kind)
+        };
+        match (self.args.bin, self.args.example) {
+            (None, None) => true,
+            (bin, example) => is("bin", bin) || is("example", example),
+        }
+    }
+
+    /// Builds what `cargo run` runs, without running it.
+    pub fn exec_build(&self) -> Result<(), Error> {
+        let mut args = self.args.cargo_args().to_vec();
+        args[0] = "build";
+        subprocess::run_attached("cargo build", &mut self.command(&args, &[]))
+    }
+
+    /// Spawns cargo with its output piped, see `Observatory::watch`.
+    pub fn spawn(&self) -> Result<Child, Error> {
+        let mut cmd = self.cargo_command();
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        cmd.spawn()
+            .map_err(|err| subprocess::spawn_failed("cargo run", &cmd, err))
+    }
+}
+
+/// Returns `true` if `profile` is or inherits from the release profile,
+/// custom profiles are looked up in the root `manifest`.
+fn inherits_release(manifest: &str, profile: &str) -> bool {
+    let manifest: Option<toml::Value> = toml::from_str(manifest).ok();
+    let mut profile = profile.to_string();
+    // Bounded in case of an inheritance cycle
+    for _ in 0..8 {
+        match profile.as_str() {
+            "release" | "bench" => return true,
+            "dev" | "test" => return false,
+            _ => {}
+        }
+        let inherits = manifest
+            .as_ref()
+            .and_then(|manifest| manifest.get("profile"))
+            .and_then(|profiles| profiles.get(&profile))
+            .and_then(|profile| profile.get("inherits"))
+            .and_then(|inherits| inherits.as_str());
+        profile = match inherits {
+            Some(inherits) => inherits.to_string(),
+            None => return false,
+        };
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+[package]
+name = "app"
+version = "0.1.0"
+
+[profile.release-lto]
+inherits = "release"
+lto = true
+
+[profile.dist]
+inherits = "release-lto"
+
+[profile.dev-opt]
+inherits = "dev"
+opt-level = 1
+
+[profile.cycle-a]
+inherits = "cycle-b"
+
+[profile.cycle-b]
+inherits = "cycle-a"
+"#;
+
+    #[test]
+    fn builtin_profiles() {
+        assert!(inherits_release("", "release"));
+        assert!(inherits_release("", "bench"));
+        assert!(!inherits_release("", "dev"));
+        assert!(!inherits_release("", "test"));
     }
 
-    pub fn spawn(&self) -> Result<(), Error> {
-        self.cargo_command().spawn().expect("Success");
-        Ok(())
+    #[test]
+    fn custom_profiles() {
+        assert!(inherits_release(MANIFEST, "release-lto"));
+        assert!(inherits_release(MANIFEST, "dist"));
+        assert!(!inherits_release(MANIFEST, "dev-opt"));
+        assert!(!inherits_release(MANIFEST, "unknown"));
+        assert!(!inherits_release(MANIFEST, "cycle-a"));
     }
 }
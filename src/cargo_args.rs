@@ -0,0 +1,199 @@
+/// Options of cargo that take a value, the value may follow as the next
+/// argument or after a `=`.
+const TAKES_VALUE: &[&str] = &[
+    "--bench",
+    "--bin",
+    "--color",
+    "--config",
+    "--example",
+    "--exclude",
+    "--features",
+    "--jobs",
+    "--manifest-path",
+    "--message-format",
+    "--package",
+    "--profile",
+    "--target",
+    "--target-dir",
+    "--test",
+    "-F",
+    "-Z",
+    "-j",
+    "-p",
+];
+
+/// The arguments passed to cargo, parsed into the parts cargo-flutter
+/// depends on.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CargoArgs<'a> {
+    /// Arguments to cargo up to `--`, without `--target-dir`.
+    args: Vec<&'a str>,
+    pub command: Option<&'a str>,
+    pub packages: Vec<&'a str>,
+    pub workspace: bool,
+    pub exclude: Vec<&'a str>,
+    pub target: Option<&'a str>,
+    pub target_dir: Option<&'a str>,
+    pub release: bool,
+    pub profile: Option<&'a str>,
+    pub features: Vec<&'a str>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+    pub bin: Option<&'a str>,
+    pub example: Option<&'a str>,
+    pub offline: bool,
+    pub quiet: bool,
+    /// Arguments after `--`, which are passed to the app.
+    pub app_args: Vec<&'a str>,
+}
+
+impl<'a> CargoArgs<'a> {
+    pub fn parse(args: &[&'a str]) -> Self {
+        let mut parsed = Self::default();
+        let mut args = args.iter().cloned();
+        parsed.command = args.next();
+        parsed.args.extend(parsed.command);
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                parsed.app_args = args.collect();
+                break;
+            }
+            let (flag, inline) = split_flag(arg);
+            let value = if inline.is_none() && TAKES_VALUE.contains(&flag) {
+                args.next()
+            } else {
+                inline
+            };
+            if flag == "--target-dir" {
+                parsed.target_dir = value;
+                continue;
+            }
+            parsed.args.push(arg);
+            if inline.is_none() {
+                parsed.args.extend(value);
+            }
+            match (flag, value) {
+                ("--package", Some(package)) | ("-p", Some(package)) => {
+                    parsed.packages.push(package)
+                }
+                ("--workspace", _) | ("--all", _) => parsed.workspace = true,
+                ("--exclude", Some(package)) => parsed.exclude.push(package),
+                ("--target", target) => parsed.target = target,
+                ("--profile", profile) => parsed.profile = profile,
+                ("--features", Some(features)) | ("-F", Some(features)) => parsed
+                    .features
+                    .extend(features.split(&[',', ' '][..]).filter(|f| !f.is_empty())),
+                ("--bin", bin) => parsed.bin = bin,
+                ("--example", example) => parsed.example = example,
+                ("--release", _) => parsed.release = true,
+                ("--all-features", _) => parsed.all_features = true,
+                ("--no-default-features", _) => parsed.no_default_features = true,
+                ("--offline", _) => parsed.offline = true,
+                ("--quiet", _) | ("-q", _) => parsed.quiet = true,
+                _ => {}
+            }
+        }
+        parsed
+    }
+
+    /// Arguments to cargo up to `--`, without `--target-dir`.
+    pub fn cargo_args(&self) -> &[&'a str] {
+        &self.args
+    }
+}
+
+/// Splits `--flag=value`, `-pvalue` and `-p=value` into the flag and its
+/// value.
+fn split_flag(arg: &str) -> (&str, Option<&str>) {
+    if arg.starts_with("--") {
+        let mut split = arg.splitn(2, '=');
+        return (split.next().unwrap(), split.next());
+    }
+    if arg.starts_with('-') && arg.len() > 2 && arg.is_char_boundary(2) {
+        let (flag, value) = arg.split_at(2);
+        if TAKES_VALUE.contains(&flag) {
+            return (flag, Some(value.trim_start_matches('=')));
+        }
+    }
+    (arg, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_after_equals() {
+        let args = CargoArgs::parse(&["build", "--target=aarch64-linux-android"]);
+        assert_eq!(args.target, Some("aarch64-linux-android"));
+        assert_eq!(
+            args.cargo_args(),
+            ["build", "--target=aarch64-linux-android"]
+        );
+
+        let args = CargoArgs::parse(&["build", "--target", "aarch64-linux-android"]);
+        assert_eq!(args.target, Some("aarch64-linux-android"));
+    }
+
+    #[test]
+    fn short_package() {
+        assert_eq!(CargoArgs::parse(&["build", "-p=foo"]).packages, ["foo"]);
+        assert_eq!(CargoArgs::parse(&["build", "-pfoo"]).packages, ["foo"]);
+        assert_eq!(CargoArgs::parse(&["build", "-p", "foo"]).packages, ["foo"]);
+        let args = CargoArgs::parse(&["build", "-p", "foo", "--package=bar"]);
+        assert_eq!(args.packages, ["foo", "bar"]);
+    }
+
+    #[test]
+    fn profile() {
+        let args = CargoArgs::parse(&["build", "--profile", "release-lto"]);
+        assert_eq!(args.profile, Some("release-lto"));
+        assert!(!args.release);
+        assert_eq!(args.cargo_args(), ["build", "--profile", "release-lto"]);
+        assert_eq!(
+            CargoArgs::parse(&["build", "--profile=release-lto"]).profile,
+            Some("release-lto")
+        );
+    }
+
+    #[test]
+    fn app_args() {
+        let args = CargoArgs::parse(&["run", "--", "--release", "--target=x"]);
+        assert!(!args.release);
+        assert_eq!(args.target, None);
+        assert_eq!(args.cargo_args(), ["run"]);
+        assert_eq!(args.app_args, ["--release", "--target=x"]);
+    }
+
+    #[test]
+    fn target_dir_is_stripped() {
+        let args = CargoArgs::parse(&["build", "--target-dir", "out", "--release"]);
+        assert_eq!(args.target_dir, Some("out"));
+        assert!(args.release);
+        assert_eq!(args.cargo_args(), ["build", "--release"]);
+
+        let args = CargoArgs::parse(&["build", "--target-dir=out"]);
+        assert_eq!(args.target_dir, Some("out"));
+        assert_eq!(args.cargo_args(), ["build"]);
+    }
+
+    #[test]
+    fn features() {
+        let args = CargoArgs::parse(&["build", "--features", "a,b c", "-Fd"]);
+        assert_eq!(args.features, ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn quiet() {
+        assert!(CargoArgs::parse(&["run", "-q"]).quiet);
+        assert!(CargoArgs::parse(&["run", "--quiet"]).quiet);
+        assert!(!CargoArgs::parse(&["run", "--", "-q"]).quiet);
+    }
+
+    #[test]
+    fn workspace() {
+        let args = CargoArgs::parse(&["build", "--all", "--exclude", "foo"]);
+        assert!(args.workspace);
+        assert_eq!(args.exclude, ["foo"]);
+    }
+}
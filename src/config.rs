@@ -1,6 +1,12 @@
 use crate::cargo::Cargo;
 use crate::error::Error;
+use crate::flutter::{BuildOptions, Flutter};
+use crate::target::{TargetSpec, Targets};
+use cargo::core::Package;
+use semver::{Version, VersionReq};
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct TomlConfig {
@@ -23,23 +29,144 @@ pub struct TomlMetadata {
 #[derive(Debug, Default, Clone, Deserialize)]
 pub struct TomlFlutter {
     pub engine_version: Option<String>,
+    pub engine_sha256: Option<HashMap<String, String>>,
+    pub engine_url: Option<String>,
+    pub local_engine_path: Option<String>,
+    pub targets: Option<HashMap<String, TargetSpec>>,
+    pub entrypoints: Option<HashMap<String, String>>,
+    pub defines: Option<HashMap<String, String>>,
+    pub flavor: Option<String>,
+    pub tree_shake_icons: Option<bool>,
+    pub obfuscate: Option<bool>,
+    pub split_debug_info: Option<String>,
+    /// Supported flutter sdk versions, for example `">= 1.12.13, < 1.15"`.
+    pub flutter_version: Option<String>,
+    /// Supported flutter channels, for example `["stable", "beta"]`.
+    pub flutter_channels: Option<Vec<String>>,
 }
 
 impl TomlConfig {
     pub fn load(cargo: &Cargo) -> Result<Self, Error> {
-        let package = cargo.package()?;
+        Self::load_package(cargo.package()?)
+    }
+
+    pub fn load_package(package: &Package) -> Result<Self, Error> {
         let bytes = std::fs::read(package.manifest_path())?;
         let string = std::str::from_utf8(&bytes)?;
-        Ok(toml::from_str(string)?)
+        toml::from_str(string)
+            .map_err(|error| Error::InvalidManifest(package.manifest_path().to_owned(), error))
     }
 
     pub fn metadata(&self) -> TomlMetadata {
         self.package.metadata.clone().unwrap_or_default()
     }
+
+    /// Returns `true` if the package has a `[package.metadata.flutter]`
+    /// section.
+    pub fn is_flutter_app(&self) -> bool {
+        self.metadata().flutter.is_some()
+    }
 }
 
 impl TomlMetadata {
     pub fn engine_version(&self) -> Option<String> {
         self.flutter.clone().unwrap_or_default().engine_version
     }
+
+    pub fn engine_url(&self) -> Option<String> {
+        self.flutter.clone().unwrap_or_default().engine_url
+    }
+
+    pub fn local_engine_path(&self) -> Option<String> {
+        self.flutter.clone().unwrap_or_default().local_engine_path
+    }
+
+    /// Build options declared in `[package.metadata.flutter]`.
+    pub fn build_options(&self) -> BuildOptions {
+        let flutter = self.flutter.clone().unwrap_or_default();
+        BuildOptions {
+            dart_defines: flutter.defines.unwrap_or_default().into_iter().collect(),
+            flavor: flutter.flavor,
+            tree_shake_icons: flutter.tree_shake_icons.unwrap_or_default(),
+            obfuscate: flutter.obfuscate.unwrap_or_default(),
+            split_debug_info: flutter.split_debug_info.map(PathBuf::from),
+        }
+    }
+
+    /// Dart entrypoints declared in `[package.metadata.flutter.entrypoints]`
+    /// by name.
+    pub fn entrypoints(&self) -> BTreeMap<String, String> {
+        self.flutter
+            .as_ref()
+            .and_then(|flutter| flutter.entrypoints.clone())
+            .unwrap_or_default()
+            .into_iter()
+            .collect()
+    }
+
+    /// Looks up a dart entrypoint declared in `[package.metadata.flutter.entrypoints]`.
+    pub fn entrypoint(&self, name: &str) -> Result<String, Error> {
+        let entrypoints = self.entrypoints();
+        entrypoints
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::EntrypointNotFound {
+                name: name.to_string(),
+                available: entrypoints.keys().cloned().collect(),
+            })
+    }
+
+    /// Builtin targets extended by `[package.metadata.flutter.targets]`.
+    pub fn targets(&self) -> Targets {
+        let mut targets = Targets::builtin();
+        if let Some(specs) = self.flutter.as_ref().and_then(|f| f.targets.clone()) {
+            targets.extend(specs);
+        }
+        targets
+    }
+
+    /// Fails if the flutter sdk isn't one of the versions and channels
+    /// declared in `[package.metadata.flutter]`. Pre-releases are compared
+    /// by their release version.
+    pub fn check_flutter_version(&self, flutter: &Flutter) -> Result<(), Error> {
+        let metadata = self.flutter.clone().unwrap_or_default();
+        if metadata.flutter_version.is_none() && metadata.flutter_channels.is_none() {
+            return Ok(());
+        }
+        let sdk = flutter.version()?;
+        if let Some(required) = &metadata.flutter_version {
+            let req = VersionReq::parse(required)
+                .map_err(|err| Error::InvalidVersion(format!("'{}': {}", required, err)))?;
+            let mut version = Version::parse(&sdk.version)
+                .map_err(|err| Error::InvalidVersion(format!("'{}': {}", sdk.version, err)))?;
+            version.pre.clear();
+            if !req.matches(&version) {
+                return Err(Error::UnsupportedFlutterVersion {
+                    version: sdk.version,
+                    required: required.clone(),
+                });
+            }
+        }
+        if let Some(channels) = &metadata.flutter_channels {
+            let supported = sdk
+                .channel
+                .as_ref()
+                .map(|channel| channels.contains(channel))
+                .unwrap_or_default();
+            if !supported {
+                return Err(Error::UnsupportedFlutterChannel {
+                    channel: sdk.channel,
+                    supported: channels.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn engine_sha256(&self, platform: &str) -> Option<String> {
+        self.flutter
+            .as_ref()
+            .and_then(|flutter| flutter.engine_sha256.as_ref())
+            .and_then(|sha256| sha256.get(platform).cloned())
+    }
 }
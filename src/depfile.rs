@@ -0,0 +1,196 @@
+use crate::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Make style dependency file as written by `flutter build bundle --depfile`
+/// and the frontend server.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Depfile {
+    outputs: Vec<PathBuf>,
+    inputs: Vec<PathBuf>,
+}
+
+impl Depfile {
+    pub fn new(outputs: Vec<PathBuf>, inputs: Vec<PathBuf>) -> Self {
+        Self { outputs, inputs }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(Self::parse(&contents))
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let mut depfile = Self::default();
+        let mut word = String::new();
+        let mut in_inputs = false;
+        let mut chars = contents.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.peek() {
+                    Some(' ') | Some('\\') | Some('#') => word.push(chars.next().unwrap()),
+                    // Line continuation
+                    Some('\n') => {
+                        chars.next();
+                    }
+                    Some('\r') => {
+                        chars.next();
+                        chars.next();
+                    }
+                    _ => word.push(c),
+                },
+                // Separates the outputs from the inputs, windows drive letters
+                // are never followed by whitespace.
+                ':' if !in_inputs && chars.peek().map(|c| c.is_whitespace()).unwrap_or(true) => {
+                    depfile.push(&mut word, in_inputs);
+                    in_inputs = true;
+                }
+                c if c.is_whitespace() => depfile.push(&mut word, in_inputs),
+                c => word.push(c),
+            }
+        }
+        depfile.push(&mut word, in_inputs);
+        depfile
+    }
+
+    fn push(&mut self, word: &mut String, input: bool) {
+        if word.is_empty() {
+            return;
+        }
+        let path = PathBuf::from(std::mem::take(word));
+        if input {
+            self.inputs.push(path);
+        } else {
+            self.outputs.push(path);
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let escape = |paths: &[PathBuf]| {
+            paths
+                .iter()
+                .map(|path| {
+                    path.display()
+                        .to_string()
+                        .replace('\\', "\\\\")
+                        .replace(' ', "\\ ")
+                        .replace('#', "\\#")
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let contents = format!("{}: {}\n", escape(&self.outputs), escape(&self.inputs));
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+
+    pub fn outputs(&self) -> &[PathBuf] {
+        &self.outputs
+    }
+
+    pub fn inputs(&self) -> &[PathBuf] {
+        &self.inputs
+    }
+
+    pub fn add_input(&mut self, path: PathBuf) {
+        self.inputs.push(path);
+    }
+
+    /// Returns `true` if all outputs exist and are newer than all inputs.
+    /// Relative paths are resolved against `root`.
+    pub fn is_up_to_date(&self, root: &Path) -> bool {
+        if self.outputs.is_empty() {
+            return false;
+        }
+        // A missing file means the outputs need to be rebuilt.
+        let modified = |paths: &[PathBuf]| -> Option<Vec<SystemTime>> {
+            paths
+                .iter()
+                .map(|path| {
+                    std::fs::metadata(root.join(path))
+                        .and_then(|metadata| metadata.modified())
+                        .ok()
+                })
+                .collect()
+        };
+        match (modified(&self.outputs), modified(&self.inputs)) {
+            (Some(outputs), Some(inputs)) => outputs.iter().min() >= inputs.iter().max(),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn parse() {
+        let depfile = Depfile::parse("build/app.dill: lib/main.dart lib/src/a.dart\n");
+        assert_eq!(depfile.outputs(), &paths(&["build/app.dill"])[..]);
+        assert_eq!(
+            depfile.inputs(),
+            &paths(&["lib/main.dart", "lib/src/a.dart"])[..]
+        );
+    }
+
+    #[test]
+    fn parse_escapes() {
+        let depfile = Depfile::parse(r"out\ dir/app.so: my\ app/main.dart a\\b.dart c\#d.dart e\f");
+        assert_eq!(depfile.outputs(), &paths(&["out dir/app.so"])[..]);
+        assert_eq!(
+            depfile.inputs(),
+            &paths(&["my app/main.dart", r"a\b.dart", "c#d.dart", r"e\f"])[..]
+        );
+    }
+
+    #[test]
+    fn parse_line_continuations() {
+        let depfile = Depfile::parse("app.so: \\\n  a.dart \\\r\n  b.dart\\\n");
+        assert_eq!(depfile.outputs(), &paths(&["app.so"])[..]);
+        assert_eq!(depfile.inputs(), &paths(&["a.dart", "b.dart"])[..]);
+    }
+
+    #[test]
+    fn parse_drive_letters() {
+        let depfile = Depfile::parse(r"C:\out\app.so D:\app.dill: C:\src\main.dart D:\a.dart");
+        assert_eq!(
+            depfile.outputs(),
+            &paths(&[r"C:\out\app.so", r"D:\app.dill"])[..]
+        );
+        assert_eq!(
+            depfile.inputs(),
+            &paths(&[r"C:\src\main.dart", r"D:\a.dart"])[..]
+        );
+    }
+
+    #[test]
+    fn parse_without_inputs() {
+        let depfile = Depfile::parse("app.so:\n");
+        assert_eq!(depfile.outputs(), &paths(&["app.so"])[..]);
+        assert!(depfile.inputs().is_empty());
+    }
+
+    #[test]
+    fn save_round_trip() {
+        let depfile = Depfile::new(
+            paths(&["out dir/app.so", r"C:\out\app.dill"]),
+            paths(&[
+                "my app/main.dart",
+                r"a\ b.dart",
+                "c#d.dart",
+                r"D:\src\e.dart",
+            ]),
+        );
+        let path =
+            std::env::temp_dir().join(format!("cargo-flutter-depfile-{}.d", std::process::id()));
+        depfile.save(&path).unwrap();
+        let loaded = Depfile::load(&path);
+        std::fs::remove_file(&path).ok();
+        assert_eq!(loaded.unwrap(), depfile);
+    }
+}
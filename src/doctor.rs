@@ -0,0 +1,322 @@
+use crate::config::TomlMetadata;
+use crate::engine::{Build, Engine};
+use crate::flutter::Flutter;
+use crate::target::{TargetSpec, Targets};
+use serde::Serialize;
+use std::path::Path;
+use std::process::Command;
+
+/// Tools used to create the packaging formats, apks are built with the
+/// android sdk.
+const PACKAGING_TOOLS: &[(&str, &str, &str)] = &[
+    (
+        "appimage",
+        "appimagetool",
+        "Download appimagetool from https://github.com/AppImage/AppImageKit/releases and put it on PATH",
+    ),
+    ("dmg", "hdiutil", "hdiutil ships with macOS"),
+    ("lipo", "lipo", "Install the Xcode command line tools with `xcode-select --install`"),
+];
+
+/// Result of one of the checks of `cargo flutter doctor`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    /// What was found, or what is wrong.
+    pub message: String,
+    /// How to fix a failed check.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+}
+
+/// Checks the environment cargo-flutter depends on.
+#[derive(Clone, Debug, Default)]
+pub struct Doctor {
+    checks: Vec<Check>,
+}
+
+impl Doctor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn checks(&self) -> &[Check] {
+        &self.checks
+    }
+
+    /// Returns `true` if all checks passed.
+    pub fn is_ok(&self) -> bool {
+        self.checks.iter().all(|check| check.ok)
+    }
+
+    fn pass(&mut self, name: &str, message: String) {
+        self.checks.push(Check {
+            name: name.to_string(),
+            ok: true,
+            message,
+            fix: None,
+        });
+    }
+
+    fn fail(&mut self, name: &str, message: String, fix: &str) {
+        self.checks.push(Check {
+            name: name.to_string(),
+            ok: false,
+            message,
+            fix: Some(fix.to_string()),
+        });
+    }
+
+    /// Checks the flutter sdk and returns the engine version it uses.
+    pub fn flutter(&mut self) -> Option<String> {
+        let flutter = match Flutter::new() {
+            Ok(flutter) => flutter,
+            Err(err) => {
+                self.fail(
+                    "flutter sdk",
+                    err.to_string(),
+                    "Install flutter and add its `bin` directory to PATH or set FLUTTER_ROOT",
+                );
+                return None;
+            }
+        };
+        match flutter.flutter() {
+            Ok(path) => self.pass("flutter", path.display().to_string()),
+            Err(err) => self.fail(
+                "flutter",
+                err.to_string(),
+                "Add the `bin` directory of the flutter sdk to PATH",
+            ),
+        }
+        match flutter.engine_version() {
+            Ok(version) => {
+                self.pass(
+                    "flutter sdk",
+                    format!("{} (engine {})", flutter.root().display(), version),
+                );
+                Some(version)
+            }
+            Err(err) => {
+                self.fail(
+                    "flutter sdk",
+                    format!(
+                        "Couldn't read the engine version of {}: {}",
+                        flutter.root().display(),
+                        err
+                    ),
+                    "Run `flutter precache` to finish installing the sdk",
+                );
+                None
+            }
+        }
+    }
+
+    /// Checks the flutter sdk against the versions and channels declared
+    /// in `[package.metadata.flutter]`.
+    pub fn flutter_version(&mut self, metadata: &TomlMetadata) {
+        let flutter = match Flutter::new() {
+            Ok(flutter) => flutter,
+            // Already reported by `flutter`
+            Err(_) => return,
+        };
+        let version = match flutter.version() {
+            Ok(version) => version,
+            Err(err) => {
+                self.fail(
+                    "flutter version",
+                    err.to_string(),
+                    "Run `flutter --version` to finish installing the sdk",
+                );
+                return;
+            }
+        };
+        let message = match &version.channel {
+            Some(channel) => format!("{} ({})", version.version, channel),
+            None => version.version.clone(),
+        };
+        match metadata.check_flutter_version(&flutter) {
+            Ok(()) => self.pass("flutter version", message),
+            Err(err) => self.fail(
+                "flutter version",
+                err.to_string(),
+                "Switch the flutter sdk with `flutter version` or `flutter channel`",
+            ),
+        }
+    }
+
+    /// Checks that `triple` is a supported target and returns its spec.
+    pub fn target<'a>(&mut self, targets: &'a Targets, triple: &str) -> Option<&'a TargetSpec> {
+        let name = format!("target {}", triple);
+        match targets.get(triple) {
+            Ok(spec) => {
+                self.pass(&name, spec.platform("{build}"));
+                Some(spec)
+            }
+            Err(err) => {
+                self.fail(
+                    &name,
+                    err.to_string(),
+                    "Declare the target in `[package.metadata.flutter.targets]`",
+                );
+                None
+            }
+        }
+    }
+
+    /// Checks that an engine version `pinned` in `Cargo.toml` or with
+    /// `FLUTTER_ENGINE_VERSION` matches the one of the sdk.
+    pub fn engine_version(&mut self, sdk: Option<&str>, pinned: Option<&str>) {
+        match (sdk, pinned) {
+            (Some(sdk), Some(pinned)) if sdk != pinned => self.fail(
+                "engine version",
+                format!("{} is pinned but the flutter sdk uses {}", pinned, sdk),
+                "Remove the pinned `engine_version` or switch the flutter sdk to the matching \
+                 version",
+            ),
+            (_, Some(version)) | (Some(version), None) => {
+                self.pass("engine version", version.to_string())
+            }
+            (None, None) => self.fail(
+                "engine version",
+                "Unknown".to_string(),
+                "Install the flutter sdk or set FLUTTER_ENGINE_VERSION",
+            ),
+        }
+    }
+
+    /// Checks that `engines` are in the cache.
+    pub fn engines(&mut self, engines: &[Engine]) {
+        for engine in engines {
+            let build = match engine.build() {
+                Build::Debug => "debug",
+                Build::Release => "release",
+                Build::Profile => "profile",
+            };
+            let name = format!("engine {} {}", engine.target(), build);
+            if engine.is_complete() {
+                self.pass(&name, engine.engine_dir().display().to_string());
+            } else {
+                self.fail(
+                    &name,
+                    format!("Not cached in {}", engine.engine_dir().display()),
+                    &format!(
+                        "Run `cargo flutter engine fetch --target {} --build {}` or build once \
+                         while online",
+                        engine.target(),
+                        build
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Checks that the rust standard library is installed for `triples`.
+    pub fn rust_targets(&mut self, triples: &[String]) {
+        let output = Command::new("rustup")
+            .args(["target", "list", "--installed"])
+            .output();
+        let installed = match output {
+            Ok(output) if output.status.success() => {
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            }
+            Ok(output) => {
+                self.fail(
+                    "rustup",
+                    String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                    "Check your rustup installation with `rustup show`",
+                );
+                return;
+            }
+            Err(err) => {
+                self.fail(
+                    "rustup",
+                    err.to_string(),
+                    "Install rustup from https://rustup.rs",
+                );
+                return;
+            }
+        };
+        for triple in triples {
+            let name = format!("rust target {}", triple);
+            if installed.lines().any(|line| line.trim() == triple) {
+                self.pass(&name, "Installed".to_string());
+            } else {
+                self.fail(
+                    &name,
+                    "Not installed".to_string(),
+                    &format!("Run `rustup target add {}`", triple),
+                );
+            }
+        }
+    }
+
+    /// Checks the tools used to package `spec`, apks are checked with
+    /// `android`.
+    pub fn packaging_tools(&mut self, spec: &TargetSpec) {
+        for (format, tool, fix) in PACKAGING_TOOLS {
+            if !spec.supports_format(format) {
+                continue;
+            }
+            let name = format!("{} ({})", tool, format);
+            match which::which(tool) {
+                Ok(path) => self.pass(&name, path.display().to_string()),
+                Err(_) => self.fail(&name, "Not found on PATH".to_string(), fix),
+            }
+        }
+    }
+
+    /// Checks the android sdk and ndk used to build apks.
+    pub fn android(&mut self) {
+        let sdk = ["ANDROID_SDK_HOME", "ANDROID_HOME"]
+            .iter()
+            .find_map(|var| std::env::var(var).ok().map(|path| (*var, path)));
+        match sdk {
+            Some((var, path)) if Path::new(&path).join("build-tools").is_dir() => {
+                self.pass("android sdk", format!("{} ({})", path, var))
+            }
+            Some((var, path)) => self.fail(
+                "android sdk",
+                format!("{} points to {} which has no build-tools", var, path),
+                "Install the build-tools with `sdkmanager \"build-tools;<version>\"`",
+            ),
+            None => self.fail(
+                "android sdk",
+                "Neither ANDROID_SDK_HOME nor ANDROID_HOME is set".to_string(),
+                "Set ANDROID_HOME to the path of the android sdk",
+            ),
+        }
+        match std::env::var("NDK_HOME") {
+            Ok(path) if Path::new(&path).is_dir() => {
+                self.pass("android ndk", format!("{} (NDK_HOME)", path))
+            }
+            Ok(path) => self.fail(
+                "android ndk",
+                format!("NDK_HOME points to {} which doesn't exist", path),
+                "Install the ndk with `sdkmanager ndk-bundle` and point NDK_HOME at it",
+            ),
+            Err(_) => self.fail(
+                "android ndk",
+                "NDK_HOME is not set".to_string(),
+                "Set NDK_HOME to the path of the android ndk",
+            ),
+        }
+    }
+
+    /// Prints a report of the checks.
+    pub fn print(&self) {
+        for check in &self.checks {
+            let status = if check.ok { "ok" } else { "!!" };
+            println!("[{}] {}: {}", status, check.name, check.message);
+            if let Some(fix) = &check.fix {
+                println!("     {}", fix);
+            }
+        }
+        let failed = self.checks.iter().filter(|check| !check.ok).count();
+        if failed == 0 {
+            println!("\nNo issues found");
+        } else {
+            println!("\n{} issue(s) found", failed);
+        }
+    }
+}
@@ -1,15 +1,34 @@
 use crate::error::Error;
+use crate::target::{TargetSpec, Targets};
+use cargo::util::Sha256;
 use curl::easy::Easy;
 use exitfailure::ExitFailure;
-use std::fs::File;
+use std::cell::Cell;
+use std::fs::OpenOptions;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Written into the engine dir once extraction has finished.
+const COMPLETE_MARKER: &str = ".complete";
+
+/// Touched whenever a build uses the engine.
+const LAST_USED_MARKER: &str = ".last-used";
+
+/// Base url of the engine builds published by flutter-rs.
+const DEFAULT_MIRROR: &str = "https://github.com/flutter-rs/engine-builds/releases/download";
+
+/// Path of an engine archive relative to a mirror.
+const MIRROR_PATH: &str = "f-{version}/{platform}.zip";
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Engine {
     version: String,
     target: String,
     build: Build,
+    url_template: Option<String>,
+    local_path: Option<PathBuf>,
+    spec: Option<TargetSpec>,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -29,111 +48,336 @@ impl Build {
     }
 }
 
+impl std::str::FromStr for Build {
+    type Err = Error;
+
+    fn from_str(build: &str) -> Result<Self, Self::Err> {
+        match build {
+            "debug" | "debug_unopt" => Ok(Self::Debug),
+            "release" => Ok(Self::Release),
+            "profile" => Ok(Self::Profile),
+            _ => Err(Error::UnknownBuild(build.to_string())),
+        }
+    }
+}
+
+/// Directory containing all downloaded engines.
+pub fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .expect("Cannot get cache dir")
+        .join("flutter-engine")
+}
+
 impl Engine {
     pub fn new(version: String, target: String, build: Build) -> Engine {
+        let spec = Targets::builtin().get(&target).ok().cloned();
         Engine {
             version,
             target,
             build,
+            url_template: None,
+            local_path: None,
+            spec,
         }
     }
 
-    pub fn download_url(&self) -> String {
-        let build = self.build.build();
-        let platform = match self.target.as_str() {
-            "x86_64-unknown-linux-gnu" => format!("linux_x64-host_{}", build),
-            "armv7-linux-androideabi" => format!("linux_x64-android_{}", build),
-            "aarch64-linux-android" => format!("linux_x64-android_{}_arm64", build),
-            "i686-linux-android" => format!("linux_x64-android_{}_x64", build),
-            "x86_64-linux-android" => format!("linux_x64-android_{}_x86", build),
-            "x86_64-apple-darwin" => format!("macosx_x64-host_{}", build),
-            "armv7-apple-ios" => format!("macosx_x64-ios_{}_arm", build),
-            "aarch64-apple-ios" => format!("macosx_x64-ios_{}", build),
-            "x86_64-pc-windows-msvc" => format!("windows_x64-host_{}", build),
-            _ => panic!("unsupported platform"),
-        };
-        format!(
-            "https://github.com/flutter-rs/engine-builds/releases/download/f-{0}/{1}.zip",
-            &self.version, platform
-        )
-    }
-
-    pub fn library_name(&self) -> &'static str {
-        match self.target.as_str() {
-            "x86_64-unknown-linux-gnu" => "libflutter_engine.so",
-            "armv7-linux-androideabi" => "libflutter_engine.so",
-            "aarch64-linux-android" => "libflutter_engine.so",
-            "i686-linux-android" => "libflutter_engine.so",
-            "x86_64-linux-android" => "libflutter_engine.so",
-            "x86_64-apple-darwin" => "libflutter_engine.dylib",
-            "armv7-apple-ios" => "libflutter_engine.dylib",
-            "aarch64-apple-ios" => "libflutter_engine.dylib",
-            "x86_64-pc-windows-msvc" => "flutter_engine.dll",
-            _ => panic!("unsupported platform"),
+    /// Uses a target spec from `[package.metadata.flutter.targets]`.
+    pub fn set_target_spec(&mut self, spec: TargetSpec) {
+        self.spec = Some(spec);
+    }
+
+    pub fn target_spec(&self) -> Result<&TargetSpec, Error> {
+        match &self.spec {
+            Some(spec) => Ok(spec),
+            None => Err(Error::UnsupportedTarget {
+                target: self.target.clone(),
+                supported: Targets::builtin().triples(),
+            }),
+        }
+    }
+
+    /// Uses a locally built engine, for example `engine/src/out/host_debug_unopt`,
+    /// instead of downloading one.
+    pub fn set_local_path(&mut self, path: PathBuf) -> Result<(), Error> {
+        if !path.is_dir() {
+            return Err(Error::LocalEngineNotFound(path));
         }
+        self.local_path = Some(path);
+        Ok(())
+    }
+
+    pub fn is_local(&self) -> bool {
+        self.local_path.is_some()
+    }
+
+    /// Overrides where the engine is downloaded from. The template may
+    /// contain `{version}`, `{platform}` and `{build}` placeholders. A
+    /// template without placeholders is treated as the base url of a mirror.
+    pub fn set_url_template(&mut self, template: String) {
+        self.url_template = Some(template);
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    pub fn build(&self) -> Build {
+        self.build
+    }
+
+    pub fn platform(&self) -> Result<String, Error> {
+        Ok(self.target_spec()?.platform(self.build.build()))
+    }
+
+    pub fn download_url(&self) -> Result<String, Error> {
+        let template = match &self.url_template {
+            Some(template) if template.contains('{') => template.clone(),
+            Some(mirror) => format!("{}/{}", mirror.trim_end_matches('/'), MIRROR_PATH),
+            None => format!("{}/{}", DEFAULT_MIRROR, MIRROR_PATH),
+        };
+        Ok(template
+            .replace("{version}", &self.version)
+            .replace("{platform}", &self.platform()?)
+            .replace("{build}", self.build.build()))
+    }
+
+    pub fn checksum_url(&self) -> Result<String, Error> {
+        Ok(format!("{}.sha256", self.download_url()?))
+    }
+
+    pub fn library_name(&self) -> Result<&str, Error> {
+        Ok(self.target_spec()?.library_name())
     }
 
     pub fn engine_dir(&self) -> PathBuf {
-        dirs::cache_dir()
-            .expect("Cannot get cache dir")
-            .join("flutter-engine")
+        if let Some(local_path) = &self.local_path {
+            return local_path.clone();
+        }
+        cache_dir()
             .join(&self.version)
             .join(&self.target)
             .join(self.build.build())
     }
 
-    pub fn engine_path(&self) -> PathBuf {
-        self.engine_dir().join(self.library_name())
+    pub fn engine_path(&self) -> Result<PathBuf, Error> {
+        Ok(self.engine_dir().join(self.library_name()?))
     }
 
-    pub fn download(&self, quiet: bool) -> Result<(), ExitFailure> {
-        let url = self.download_url();
-        let path = self.engine_path();
-        let dir = path.parent().unwrap().to_owned();
+    fn download_file(&self) -> PathBuf {
+        self.engine_dir().with_extension("zip.part")
+    }
+
+    fn staging_dir(&self) -> PathBuf {
+        self.engine_dir().with_extension("staging")
+    }
 
-        if path.exists() {
+    pub fn is_complete(&self) -> bool {
+        self.is_local() || self.engine_dir().join(COMPLETE_MARKER).exists()
+    }
+
+    /// Records that a build used this engine, see `Engine::last_used`.
+    pub fn mark_used(&self) -> Result<(), Error> {
+        if self.is_local() {
             return Ok(());
         }
+        std::fs::write(self.engine_dir().join(LAST_USED_MARKER), "")?;
+        Ok(())
+    }
 
-        std::fs::create_dir_all(&dir)?;
+    pub fn last_used(&self) -> Option<SystemTime> {
+        let dir = self.engine_dir();
+        [LAST_USED_MARKER, COMPLETE_MARKER]
+            .iter()
+            .filter_map(|marker| std::fs::metadata(dir.join(marker)).ok())
+            .filter_map(|metadata| metadata.modified().ok())
+            .max()
+    }
+
+    /// Fails with a list of all engines that aren't cached and would need to
+    /// be downloaded from the network.
+    pub fn ensure_cached(engines: &[&Engine]) -> Result<(), Error> {
+        let mut missing: Vec<(String, PathBuf)> = Vec::new();
+        for engine in engines {
+            let url = engine.download_url()?;
+            if engine.is_complete() || url.starts_with("file://") {
+                continue;
+            }
+            let dir = engine.engine_dir();
+            if !missing.iter().any(|(_, missing_dir)| *missing_dir == dir) {
+                missing.push((url, dir));
+            }
+        }
+        if !missing.is_empty() {
+            return Err(Error::EnginesNotCached(missing));
+        }
+        Ok(())
+    }
+
+    pub fn download(&self, quiet: bool, sha256: Option<&str>) -> Result<(), ExitFailure> {
+        let url = self.download_url()?;
+        let dir = self.engine_dir();
+
+        if self.is_complete() {
+            return Ok(());
+        }
+
+        if !["http://", "https://", "file://"]
+            .iter()
+            .any(|scheme| url.starts_with(scheme))
+        {
+            return Err(Error::UnsupportedUrl(url).into());
+        }
+
+        std::fs::create_dir_all(dir.parent().unwrap())?;
 
         println!("Starting download from {}", url);
-        let download_file = dir.join("engine.zip");
-        let mut file = File::create(&download_file)?;
+        let download_file = self.download_file();
+        self.fetch(&url, &download_file, quiet)?;
+        println!("Download finished");
+
+        let expected = match sha256 {
+            Some(sha256) => sha256.to_owned(),
+            None => self.fetch_checksum()?,
+        };
+        if let Err(err) = verify_checksum(&download_file, &expected) {
+            std::fs::remove_file(&download_file).ok();
+            return Err(err.into());
+        }
+
+        println!("Extracting...");
+        let staging_dir = self.staging_dir();
+        std::fs::remove_dir_all(&staging_dir).ok();
+        std::fs::create_dir_all(&staging_dir)?;
+        crate::unzip::unzip(&download_file, &staging_dir)?;
+        std::fs::write(staging_dir.join(COMPLETE_MARKER), &url)?;
+
+        // An interrupted download may have left a partially populated engine dir.
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::rename(&staging_dir, &dir)?;
+        std::fs::remove_file(&download_file)?;
+
+        Ok(())
+    }
+
+    /// Downloads `url` into `path`, resuming from the end of `path` if a
+    /// previous download was interrupted.
+    fn fetch(&self, url: &str, path: &Path, quiet: bool) -> Result<(), Error> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let offset = file.metadata()?.len();
+        if offset > 0 {
+            println!("Resuming download at {} bytes", offset);
+        }
+        let mut resumed = offset > 0;
+        let status = Cell::new(0);
         let mut last_done = 0.0;
 
         let mut easy = Easy::new();
         easy.fail_on_error(true)?;
-        easy.url(&url)?;
+        easy.url(url)?;
         easy.follow_location(true)?;
+        easy.resume_from(offset)?;
         easy.progress(true)?;
-        easy.progress_function(move |total, done, _, _| {
-            if done > last_done {
-                last_done = done;
-                if !quiet {
-                    println!("Downloading flutter engine {} of {}", done, total);
-                }
-            }
-            true
-        })?;
-        easy.write_function(move |data| Ok(file.write(data).unwrap()))?;
 
-        easy.perform()
-            .or_else(|_| Err(Error::EngineNotFound(self.version.clone())))?;
-        println!("Download finished");
+        let result = {
+            let mut transfer = easy.transfer();
+            transfer.progress_function(|total, done, _, _| {
+                if done > last_done {
+                    last_done = done;
+                    if !quiet {
+                        println!("Downloading flutter engine {} of {}", done, total);
+                    }
+                }
+                true
+            })?;
+            transfer.header_function(|header| {
+                // With redirects there is one status line per response.
+                if header.starts_with(b"HTTP/") {
+                    if let Some(code) = std::str::from_utf8(header)
+                        .ok()
+                        .and_then(|line| line.split_whitespace().nth(1))
+                        .and_then(|code| code.parse().ok())
+                    {
+                        status.set(code);
+                    }
+                }
+                true
+            })?;
+            transfer.write_function(|data| {
+                // The server ignored the range request and sent the whole file.
+                if resumed && status.get() == 200 {
+                    resumed = false;
+                    if file.set_len(0).is_err() {
+                        return Ok(0);
+                    }
+                }
+                Ok(file.write(data).unwrap_or(0))
+            })?;
+            transfer.perform()
+        };
 
-        println!("Extracting...");
-        crate::unzip::unzip(&download_file, &dir)?;
+        match result {
+            Ok(()) => Ok(()),
+            // The previous attempt already received the whole archive.
+            Err(_) if offset > 0 && easy.response_code()? == 416 => Ok(()),
+            Err(_) => Err(Error::EngineNotFound(self.version.clone())),
+        }
+    }
 
-        Ok(())
+    fn fetch_checksum(&self) -> Result<String, Error> {
+        let url = self.checksum_url()?;
+        let mut data = Vec::new();
+        let mut easy = Easy::new();
+        let result = easy
+            .fail_on_error(true)
+            .and_then(|_| easy.url(&url))
+            .and_then(|_| easy.follow_location(true))
+            .and_then(|_| {
+                let mut transfer = easy.transfer();
+                transfer.write_function(|chunk| {
+                    data.extend_from_slice(chunk);
+                    Ok(chunk.len())
+                })?;
+                transfer.perform()
+            });
+        if result.is_err() {
+            return Err(Error::ChecksumNotFound(url));
+        }
+        // The manifest uses the `sha256sum` format: `<hex digest>  <file name>`.
+        std::str::from_utf8(&data)?
+            .split_whitespace()
+            .next()
+            .map(|sha256| sha256.to_owned())
+            .ok_or(Error::ChecksumNotFound(url))
     }
 
     pub fn dart(&self) -> Result<PathBuf, Error> {
         let host_engine_dir = self.engine_dir();
-        ["dart", "dart.exe"]
-            .iter()
-            .map(|bin| host_engine_dir.join(bin))
-            .find(|path| path.exists())
-            .ok_or(Error::DartNotFound)
+        [
+            "dart",
+            "dart.exe",
+            // Layout of a local engine build
+            "dart-sdk/bin/dart",
+            "dart-sdk/bin/dart.exe",
+        ]
+        .iter()
+        .map(|bin| host_engine_dir.join(bin))
+        .find(|path| path.exists())
+        .ok_or(Error::DartNotFound)
+    }
+}
+
+fn verify_checksum(path: &Path, expected: &str) -> Result<(), Error> {
+    let actual = Sha256::new().update_path(path)?.finish_hex();
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        return Err(Error::ChecksumMismatch {
+            path: path.to_owned(),
+            expected: expected.trim().to_owned(),
+            actual,
+        });
     }
+    Ok(())
 }
@@ -1,18 +1,71 @@
 #[derive(Debug)]
 pub enum Error {
     PackageNotMember,
+    InvalidManifest(std::path::PathBuf, toml::de::Error),
     EngineNotFound(String),
+    UnknownBuild(String),
+    InvalidDefine(String),
+    EntrypointNotFound {
+        name: String,
+        available: Vec<String>,
+    },
+    UnsupportedTarget {
+        target: String,
+        supported: Vec<String>,
+    },
+    LocalEngineNotFound(std::path::PathBuf),
+    LocalHostEngineNotFound(std::path::PathBuf),
+    UnsupportedUrl(String),
+    EnginesNotCached(Vec<(String, std::path::PathBuf)>),
+    ChecksumNotFound(String),
+    ChecksumMismatch {
+        path: std::path::PathBuf,
+        expected: String,
+        actual: String,
+    },
     FlutterNotFound,
     DartNotFound,
     GenSnapshotNotFound,
+    ObservatoryNotFound,
+    ObservatoryTimeout(u64),
+    AppExited(i32),
+    WatchRequiresDebug,
+    VmService {
+        method: String,
+        message: String,
+    },
+    ReloadRejected(String),
     FormatNotSupported,
-    CargoError,
-    FlutterError,
+    BinNotSelected(Vec<String>),
+    InvalidVersion(String),
+    UnsupportedFlutterVersion {
+        version: String,
+        required: String,
+    },
+    UnsupportedFlutterChannel {
+        channel: Option<String>,
+        supported: Vec<String>,
+    },
+    UnknownTemplate(String),
+    InvalidName(String),
+    FileExists(std::path::PathBuf),
+    SubprocessFailed {
+        step: String,
+        program: String,
+        args: Vec<String>,
+        /// `None` if the program didn't run to completion.
+        status: Option<std::process::ExitStatus>,
+        stderr_tail: Vec<String>,
+    },
     NotCalledWithCargo,
     Which(which::Error),
+    Curl(curl::Error),
     Io(std::io::Error),
     Toml(toml::de::Error),
     Utf8(std::str::Utf8Error),
+    WebSocket(tungstenite::Error),
+    Json(serde_json::Error),
+    Notify(notify::Error),
     Err(failure::Error),
 }
 
@@ -20,6 +73,9 @@ impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Error::PackageNotMember => write!(f, "Package is not a member of the workspace"),
+            Error::InvalidManifest(path, error) => {
+                write!(f, "Invalid manifest {}: {}", path.display(), error)
+            }
             Error::FlutterNotFound => write!(f, "Couldn't find flutter sdk"),
             Error::EngineNotFound(version) => write!(
                 f,
@@ -45,18 +101,168 @@ You'll find the available builds on our github releases page [0].
 - [0] https://github.com/flutter-rs/engine-builds/releases"#,
                 version,
             ),
+            Error::UnknownBuild(build) => write!(
+                f,
+                "Unknown engine build '{}', expected one of debug, release or profile",
+                build
+            ),
+            Error::InvalidDefine(define) => {
+                write!(f, "Invalid dart define '{}', expected KEY=VALUE", define)
+            }
+            Error::EntrypointNotFound { name, available } => write!(
+                f,
+                "Entrypoint '{}' isn't declared in `[package.metadata.flutter.entrypoints]`. \
+                 Available entrypoints: {}",
+                name,
+                available.join(", ")
+            ),
+            Error::UnsupportedTarget { target, supported } => write!(
+                f,
+                "Unsupported target '{}'. Supported targets are:\n  {}\n\nOther targets can be \
+                 added in `[package.metadata.flutter.targets]`.",
+                target,
+                supported.join("\n  ")
+            ),
+            Error::LocalEngineNotFound(path) => write!(
+                f,
+                "Couldn't find the local engine build at {}",
+                path.display()
+            ),
+            Error::LocalHostEngineNotFound(path) => write!(
+                f,
+                "Couldn't find the host engine build at {}, cross compiled local engines \
+                 need the matching host build. Pass its `out` directory with \
+                 `--local-engine-host`",
+                path.display()
+            ),
+            Error::UnsupportedUrl(url) => write!(
+                f,
+                "Unsupported engine url '{}'. Only http://, https:// and file:// are supported.",
+                url
+            ),
+            Error::EnginesNotCached(missing) => {
+                writeln!(
+                    f,
+                    "Can't download the flutter engine in offline mode. Missing cache entries:"
+                )?;
+                for (url, dir) in missing {
+                    writeln!(f, "  {}", dir.display())?;
+                    writeln!(f, "    from {}", url)?;
+                }
+                write!(
+                    f,
+                    "\nRun once without `--offline` to populate the cache, or point \
+                     `engine_url` at a local `file://` mirror."
+                )
+            }
+            Error::ChecksumNotFound(url) => write!(
+                f,
+                r#"Couldn't fetch the engine checksum manifest from '{}'.
+
+If the engine build doesn't publish a checksum you can pin it in `Cargo.toml`:
+
+```toml
+[package.metadata.flutter.engine_sha256]
+linux_x64-host_release = "..."
+```"#,
+                url
+            ),
+            Error::ChecksumMismatch {
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Checksum mismatch for {}: expected sha256 {} but got {}. The archive has been removed.",
+                path.display(),
+                expected,
+                actual
+            ),
             Error::DartNotFound => write!(f, "Could't find dart"),
             Error::GenSnapshotNotFound => write!(f, "Couldn't find gen_snapshot"),
+            Error::ObservatoryNotFound => {
+                write!(f, "The app exited before the observatory started")
+            }
+            Error::ObservatoryTimeout(secs) => write!(
+                f,
+                "The app didn't report an observatory uri within {} seconds. Make sure the \
+                 embedder passes `DART_OBSERVATORY_PORT` to the engine.",
+                secs
+            ),
+            Error::AppExited(code) => write!(f, "The app exited with code {}", code),
+            Error::WatchRequiresDebug => write!(
+                f,
+                "`--watch` hot reloads the app, which is only supported by debug builds"
+            ),
+            Error::VmService { method, message } => {
+                write!(f, "VM service call '{}' failed: {}", method, message)
+            }
+            Error::ReloadRejected(reason) => write!(f, "Hot reload was rejected: {}", reason),
             Error::FormatNotSupported => write!(f, "Format not supported"),
-            Error::CargoError => write!(f, "Cargo did not exit successfully"),
-            Error::FlutterError => write!(f, "Flutter did not exit successfully"),
+            Error::BinNotSelected(bins) => write!(
+                f,
+                "Select the executable of the package with `--bin` or `--example`. Available \
+                 executables: {}",
+                bins.join(", ")
+            ),
+            Error::InvalidVersion(version) => write!(f, "Invalid version {}", version),
+            Error::UnsupportedFlutterVersion { version, required } => write!(
+                f,
+                "This app requires flutter {} but the flutter sdk is {}. Switch to a supported \
+                 version with `flutter version <version>`.",
+                required, version
+            ),
+            Error::UnsupportedFlutterChannel { channel, supported } => write!(
+                f,
+                "This app requires the flutter {} channel but the flutter sdk is on {}. Switch \
+                 with `flutter channel {}`.",
+                supported.join(" or "),
+                channel.as_deref().unwrap_or("no channel"),
+                supported.first().map(String::as_str).unwrap_or("stable")
+            ),
+            Error::UnknownTemplate(template) => write!(
+                f,
+                "Unknown template '{}', expected one of minimal, plugins or driver",
+                template
+            ),
+            Error::InvalidName(name) => write!(
+                f,
+                "Invalid package name '{}', it must start with a letter and only contain \
+                 letters, digits, `-` and `_`. Use `--name` to choose another name.",
+                name
+            ),
+            Error::FileExists(path) => write!(
+                f,
+                "{} already exists, refusing to overwrite it",
+                path.display()
+            ),
+            Error::SubprocessFailed {
+                step,
+                program,
+                args,
+                status,
+                stderr_tail,
+            } => {
+                write!(f, "{} failed: `{} {}`", step, program, args.join(" "))?;
+                if let Some(status) = status {
+                    write!(f, " exited with {}", status)?;
+                }
+                for line in stderr_tail {
+                    write!(f, "\n  {}", line)?;
+                }
+                Ok(())
+            }
             Error::NotCalledWithCargo => {
                 write!(f, "This binary may only be called via `cargo flutter`.")
             }
             Error::Which(error) => error.fmt(f),
+            Error::Curl(error) => error.fmt(f),
             Error::Io(error) => error.fmt(f),
             Error::Toml(error) => error.fmt(f),
             Error::Utf8(error) => error.fmt(f),
+            Error::WebSocket(error) => error.fmt(f),
+            Error::Json(error) => error.fmt(f),
+            Error::Notify(error) => error.fmt(f),
             Error::Err(error) => error.fmt(f),
         }
     }
@@ -70,6 +276,12 @@ impl From<which::Error> for Error {
     }
 }
 
+impl From<curl::Error> for Error {
+    fn from(error: curl::Error) -> Self {
+        Error::Curl(error)
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(error: std::io::Error) -> Self {
         Error::Io(error)
@@ -88,6 +300,24 @@ impl From<std::str::Utf8Error> for Error {
     }
 }
 
+impl From<tungstenite::Error> for Error {
+    fn from(error: tungstenite::Error) -> Self {
+        Error::WebSocket(error)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Json(error)
+    }
+}
+
+impl From<notify::Error> for Error {
+    fn from(error: notify::Error) -> Self {
+        Error::Notify(error)
+    }
+}
+
 impl From<failure::Error> for Error {
     fn from(error: failure::Error) -> Self {
         Error::Err(error)
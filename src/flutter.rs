@@ -1,13 +1,91 @@
 use crate::cargo::Cargo;
+use crate::depfile::Depfile;
 use crate::engine::{Build, Engine};
 use crate::error::Error;
+use crate::frontend_server::{file_uri, FrontendServer};
+use crate::process::Launcher;
+use crate::subprocess;
+use crate::vmservice::VmService;
+use crate::watch::Action;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Adds the source location of widgets to their constructors, which the
+/// widget inspector of debug builds relies on.
+const TRACK_WIDGET_CREATION: &str = "--track-widget-creation";
 
 pub struct Flutter {
     root: PathBuf,
 }
 
+/// Version and channel of the flutter sdk.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FlutterVersion {
+    pub version: String,
+    /// `None` if the sdk isn't a git checkout of one of the channels.
+    pub channel: Option<String>,
+}
+
+/// Options passed through to `flutter build bundle` and the frontend server.
+#[derive(Clone, Debug, Default)]
+pub struct BuildOptions {
+    /// Compile time constants available via `String.fromEnvironment`.
+    pub dart_defines: BTreeMap<String, String>,
+    pub flavor: Option<String>,
+    pub tree_shake_icons: bool,
+    pub obfuscate: bool,
+    /// Directory the AOT snapshot's debug symbols are written to.
+    pub split_debug_info: Option<PathBuf>,
+}
+
+impl BuildOptions {
+    fn fingerprint(&self) -> String {
+        format!(
+            "{:?} {:?} {} {} {:?}",
+            self.dart_defines,
+            self.flavor,
+            self.tree_shake_icons,
+            self.obfuscate,
+            self.split_debug_info
+        )
+    }
+
+    /// Arguments of `flutter build bundle`.
+    fn bundle_args(&self) -> Vec<String> {
+        let mut args = vec![TRACK_WIDGET_CREATION.to_string()];
+        for (key, value) in &self.dart_defines {
+            args.push(format!("--dart-define={}={}", key, value));
+        }
+        if let Some(flavor) = &self.flavor {
+            args.push("--flavor".to_string());
+            args.push(flavor.clone());
+        }
+        if self.tree_shake_icons {
+            args.push("--tree-shake-icons".to_string());
+        }
+        args
+    }
+
+    /// Arguments of the incremental frontend server, the kernel it compiles
+    /// must match the one `flutter build bundle` compiled.
+    fn frontend_server_args(&self) -> Vec<String> {
+        let mut args = vec![TRACK_WIDGET_CREATION.to_string()];
+        args.extend(self.defines());
+        args
+    }
+
+    /// Defines in the form expected by the frontend server.
+    fn defines(&self) -> Vec<String> {
+        self.dart_defines
+            .iter()
+            .map(|(key, value)| format!("-D{}={}", key, value))
+            .collect()
+    }
+}
+
 impl Flutter {
     pub fn new() -> Result<Self, Error> {
         let root = if let Ok(root) = std::env::var("FLUTTER_ROOT") {
@@ -42,117 +120,323 @@ impl Flutter {
         Ok(std::fs::read_to_string(path).map(|v| v.trim().to_owned())?)
     }
 
-    pub fn bundle(&self, cargo: &Cargo, build: Build, dart_main: &Path) -> Result<(), Error> {
+    /// Reads the version from the `version` file the flutter tool writes on
+    /// its first run, and falls back to `flutter --version --machine`.
+    pub fn version(&self) -> Result<FlutterVersion, Error> {
+        let version = std::fs::read_to_string(self.root.join("version"))
+            .map(|version| version.trim().to_string())
+            .ok()
+            .filter(|version| !version.is_empty());
+        let version = match version {
+            Some(version) => version,
+            None => {
+                let mut cmd = Command::new(self.flutter()?);
+                cmd.arg("--version").arg("--machine");
+                let output = cmd
+                    .output()
+                    .map_err(|err| subprocess::spawn_failed("flutter --version", &cmd, err))?;
+                subprocess::check("flutter --version", &cmd, output.status, Vec::new())?;
+                let machine: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+                return Ok(FlutterVersion {
+                    version: machine["frameworkVersion"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    channel: machine["channel"].as_str().map(String::from),
+                });
+            }
+        };
+        // The channels are branches of the sdk checkout
+        let channel = std::fs::read_to_string(self.root.join(".git").join("HEAD"))
+            .ok()
+            .and_then(|head| {
+                head.trim()
+                    .strip_prefix("ref: refs/heads/")
+                    .map(String::from)
+            });
+        Ok(FlutterVersion { version, channel })
+    }
+
+    /// Builds `flutter_assets` into `out_dir`.
+    pub fn bundle(
+        &self,
+        cargo: &Cargo,
+        root: &Path,
+        build: Build,
+        dart_main: &Path,
+        out_dir: &Path,
+        options: &BuildOptions,
+    ) -> Result<(), Error> {
         let flag = match build {
             Build::Debug => "--debug",
             Build::Release => "--release",
             Build::Profile => "--profile",
         };
-        let status = Command::new(self.flutter()?)
-            .current_dir(cargo.workspace().root())
+        let depfile = out_dir.join("snapshot_blob.bin.d");
+        let fingerprint = format!(
+            "{} {} {} {}",
+            self.engine_version().unwrap_or_default(),
+            flag,
+            dart_main.display(),
+            options.fingerprint()
+        );
+        if is_up_to_date(root, &depfile, &fingerprint) {
+            println!("flutter_assets are up to date");
+            return Ok(());
+        }
+
+        let mut cmd = Command::new(self.flutter()?);
+        cmd.current_dir(root)
             .arg("build")
             .arg("bundle")
             .arg(flag)
-            .arg("--track-widget-creation")
+            .args(options.bundle_args())
             .arg("--asset-dir")
-            .arg(cargo.build_dir().join("flutter_assets"))
+            .arg(out_dir.join("flutter_assets"))
             .arg("--depfile")
-            .arg(cargo.build_dir().join("snapshot_blob.bin.d"))
+            .arg(&depfile)
             .arg("--target")
-            .arg(dart_main)
-            .status()
-            .expect("flutter build bundle");
-        if status.code() != Some(0) {
-            return Err(Error::FlutterError);
+            .arg(dart_main);
+        if cargo.offline() {
+            // `pub get` would try to reach pub.dev
+            cmd.arg("--no-pub");
         }
-        Ok(())
+        subprocess::run("flutter build bundle", &mut cmd)?;
+        save_fingerprint(&depfile, &fingerprint)
     }
 
-    pub fn attach(&self, cargo: &Cargo, debug_uri: &str) -> Result<(), Error> {
-        let status = Command::new(self.flutter()?)
-            .current_dir(cargo.workspace().root())
-            .arg("attach")
-            .arg("--device-id=flutter-tester")
-            .arg(format!("--debug-uri={}", debug_uri))
-            .status()
-            .expect("Success");
-        if status.code() != Some(0) {
-            return Err(Error::FlutterError);
+    /// Connects to the observatory of a debug build and hot reloads the
+    /// dart sources on request. The session ends with the app.
+    #[allow(clippy::too_many_arguments)]
+    pub fn attach(
+        &self,
+        root: &Path,
+        host_engine: &Engine,
+        dart_main: &Path,
+        out_dir: &Path,
+        options: &BuildOptions,
+        launcher: &mut Launcher,
+        actions: Receiver<Action>,
+    ) -> Result<(), Error> {
+        let dart_main = root.join(dart_main);
+        let lib_dir = root.join("lib");
+        let mut compiler = FrontendServer::start(
+            host_engine,
+            root,
+            &out_dir.join("app.dill"),
+            &options.frontend_server_args(),
+        )?;
+
+        // The app runs the bundled kernel, the first compilation only
+        // provides the base for incremental ones.
+        let mut sources = dart_sources(&lib_dir)?;
+        if compiler.compile(&dart_main)?.is_none() {
+            return Err(compiler.failed("kernel compilation"));
+        }
+        compiler.accept()?;
+
+        let mut vm = VmService::connect(launcher.uri())?;
+        println!("Flutter attached to {}", launcher.uri());
+        println!("Press r to hot reload, R to hot restart or q to quit, followed by enter.");
+
+        // Whether the app runs different dart code than the bundle
+        let mut reloaded = false;
+        loop {
+            let mut action = match actions.recv_timeout(Duration::from_millis(100)) {
+                Ok(action) => action,
+                Err(RecvTimeoutError::Timeout) if launcher.app().try_wait()?.is_some() => break,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            // Saving several files at once only needs to be handled once
+            while let Ok(next) = actions.try_recv() {
+                action = action.max(next);
+            }
+            let start = Instant::now();
+            let restart = match action {
+                Action::Quit => break,
+                Action::Reload => false,
+                Action::Restart => true,
+                Action::Relaunch => {
+                    match launcher.relaunch() {
+                        Ok(()) => {}
+                        Err(err @ Error::SubprocessFailed { .. }) => {
+                            println!("{}", err);
+                            continue;
+                        }
+                        Err(err) => return Err(err),
+                    }
+                    vm = VmService::connect(launcher.uri())?;
+                    println!("Relaunched in {}ms", start.elapsed().as_millis());
+                    // The new app runs the bundled kernel again
+                    if !reloaded {
+                        continue;
+                    }
+                    true
+                }
+            };
+
+            let current = dart_sources(&lib_dir)?;
+            let invalidated: Vec<PathBuf> = current
+                .iter()
+                .filter(|(path, modified)| sources.get(*path) != Some(modified))
+                .map(|(path, _)| path.clone())
+                .collect();
+            if restart {
+                compiler.reset()?;
+            }
+            let output = match compiler.recompile(&dart_main, &invalidated)? {
+                Some(output) if output.errors == 0 => output,
+                _ => {
+                    compiler.reject()?;
+                    println!("Compilation failed, fix the errors and try again.");
+                    continue;
+                }
+            };
+            let dill = file_uri(&root.join(&output.dill));
+            let asset_dir = file_uri(&out_dir.join("flutter_assets"));
+            let mut rejected = false;
+            for view in vm.list_views()? {
+                let result = if restart {
+                    vm.run_in_view(&view.id, &dill, &asset_dir)
+                } else if let Some(isolate) = &view.isolate {
+                    vm.reload_sources(isolate, &dill)
+                        .and_then(|_| vm.reassemble(isolate))
+                } else {
+                    Ok(())
+                };
+                match result {
+                    Ok(()) => {}
+                    Err(Error::ReloadRejected(reason)) => {
+                        println!("Hot reload was rejected:\n{}", reason);
+                        rejected = true;
+                        break;
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            // The changed files are compiled again with the next reload
+            if rejected {
+                compiler.reject()?;
+                continue;
+            }
+            compiler.accept()?;
+            sources = current;
+            reloaded = true;
+            println!(
+                "{} in {}ms",
+                if restart { "Restarted" } else { "Reloaded" },
+                start.elapsed().as_millis()
+            );
         }
         Ok(())
     }
 
+    /// Compiles `dart_main` into `out_dir/app.so`.
     pub fn aot(
         &self,
-        cargo: &Cargo,
+        root: &Path,
         host_engine: &Engine,
         target_engine: &Engine,
+        dart_main: &Path,
+        out_dir: &Path,
+        options: &BuildOptions,
     ) -> Result<(), Error> {
-        let root = cargo.workspace().root();
-        let build_dir = cargo.build_dir();
         let host_engine_dir = host_engine.engine_dir();
         let target_engine_dir = target_engine.engine_dir();
-        let snapshot = build_dir.join("kernel_snapshot.dill");
-
-        let status = Command::new(host_engine.dart()?)
-            .current_dir(root)
-            .arg(
-                host_engine_dir
-                    .join("gen")
-                    .join("frontend_server.dart.snapshot"),
-            )
+        let snapshot = out_dir.join("kernel_snapshot.dill");
+        let kernel_depfile = out_dir.join("kernel_snapshot.d");
+        let app = out_dir.join("app.so");
+        let depfile = out_dir.join("app.so.d");
+        let frontend_server = host_engine_dir
+            .join("gen")
+            .join("frontend_server.dart.snapshot");
+
+        let defines: &[&str] = match target_engine.build() {
+            Build::Profile => &["-Ddart.vm.profile=true", "-Ddart.vm.product=false"],
+            _ => &["-Ddart.vm.product=true"],
+        };
+
+        let fingerprint = format!(
+            "{} {} {} {} {}",
+            host_engine_dir.display(),
+            target_engine_dir.display(),
+            defines.join(" "),
+            dart_main.display(),
+            options.fingerprint()
+        );
+        if is_up_to_date(root, &depfile, &fingerprint) {
+            println!("{} is up to date", app.display());
+            return Ok(());
+        }
+
+        let mut cmd = Command::new(host_engine.dart()?);
+        cmd.current_dir(root)
+            .arg(&frontend_server)
             .arg("--sdk-root")
             .arg(host_engine_dir.join("flutter_patched_sdk"))
             .arg("--target=flutter")
             .arg("--aot")
             .arg("--tfa")
-            .arg("-Ddart.vm.product=true")
+            .args(defines)
+            .args(options.defines())
             .arg("--packages")
             .arg(".packages")
             .arg("--output-dill")
             .arg(&snapshot)
-            .arg(root.join("lib").join("main.dart"))
-            .status()
-            .expect("Success");
+            .arg("--depfile")
+            .arg(&kernel_depfile)
+            .arg(root.join(dart_main));
+        subprocess::run("kernel compilation", &mut cmd)?;
 
-        if status.code() != Some(0) {
-            return Err(Error::FlutterError);
-        }
+        // The target engine ships a gen_snapshot that runs on the host and
+        // generates code for the target, which is the host engine's own
+        // gen_snapshot when not cross compiling.
+        let gen_snapshot = target_engine
+            .target_spec()?
+            .gen_snapshot_names()
+            .iter()
+            .map(|bin| target_engine_dir.join(bin))
+            .find(|path| path.exists())
+            .ok_or(Error::GenSnapshotNotFound)?;
 
-        let gen_snapshot = [
-            "gen_snapshot",
-            "gen_snapshot_x64",
-            "gen_snapshot_x86",
-            "gen_snapshot_host_targeting_host",
-            "gen_snapshot.exe",
-        ]
-        .iter()
-        .map(|bin| target_engine_dir.join(bin))
-        .find(|path| path.exists())
-        .ok_or(Error::GenSnapshotNotFound)?;
-
-        let status = Command::new(gen_snapshot)
-            .current_dir(root)
+        let mut cmd = Command::new(&gen_snapshot);
+        cmd.current_dir(root)
             .arg("--causal_async_stacks")
             .arg("--deterministic")
             .arg("--snapshot_kind=app-aot-elf")
             .arg("--strip")
-            .arg(format!("--elf={}", build_dir.join("app.so").display()))
-            .arg(&snapshot)
-            .status()
-            .expect("Success");
-
-        if status.code() != Some(0) {
-            return Err(Error::FlutterError);
+            .arg(format!("--elf={}", app.display()));
+        let mut outputs = vec![app];
+        if let Some(dir) = &options.split_debug_info {
+            std::fs::create_dir_all(dir)?;
+            let symbols = dir.join(format!("app.{}.symbols", target_engine.target()));
+            cmd.arg("--dwarf-stack-traces")
+                .arg("--resolve-dwarf-paths")
+                .arg(format!("--save-debugging-info={}", symbols.display()));
+            outputs.push(symbols);
         }
+        if options.obfuscate {
+            cmd.arg("--obfuscate");
+        }
+        cmd.arg(&snapshot);
+        subprocess::run("aot snapshot", &mut cmd)?;
 
-        Ok(())
+        // Records the dart sources of the kernel snapshot and the tools used
+        // as inputs of `app.so`.
+        let kernel = Depfile::load(&kernel_depfile)?;
+        let mut app_depfile = Depfile::new(outputs, kernel.inputs().to_vec());
+        app_depfile.add_input(root.join(".packages"));
+        app_depfile.add_input(frontend_server);
+        app_depfile.add_input(gen_snapshot);
+        app_depfile.save(&depfile)?;
+        save_fingerprint(&depfile, &fingerprint)
     }
 
     pub fn drive(
         &self,
         host_engine: &Engine,
-        cargo: &Cargo,
+        root: &Path,
         debug_uri: &str,
         dart_main: &Path,
     ) -> Result<(), Error> {
@@ -162,14 +446,71 @@ impl Flutter {
 
         // used by flutter_driver
         std::env::set_var("VM_SERVICE_URL", debug_uri);
-        let status = Command::new(host_engine.dart()?)
-            .current_dir(cargo.workspace().root())
-            .arg(driver)
-            .status()
-            .expect("Success");
-        if status.code() != Some(0) {
-            return Err(Error::FlutterError);
+        let mut cmd = Command::new(host_engine.dart()?);
+        cmd.current_dir(root).arg(driver);
+        subprocess::run("flutter driver", &mut cmd)
+    }
+}
+
+/// Modification times of the dart files in `dir`.
+fn dart_sources(dir: &Path) -> Result<HashMap<PathBuf, SystemTime>, Error> {
+    let mut sources = HashMap::new();
+    if !dir.is_dir() {
+        return Ok(sources);
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            sources.extend(dart_sources(&path)?);
+        } else if path.extension().and_then(|ext| ext.to_str()) == Some("dart") {
+            sources.insert(path.clone(), std::fs::metadata(&path)?.modified()?);
         }
-        Ok(())
+    }
+    Ok(sources)
+}
+
+/// Returns `true` if the outputs recorded in `depfile` are newer than their
+/// inputs and were built with the same `fingerprint`.
+fn is_up_to_date(root: &Path, depfile: &Path, fingerprint: &str) -> bool {
+    let stamp = depfile.with_extension("stamp");
+    std::fs::read_to_string(stamp)
+        .map(|stamp| stamp == fingerprint)
+        .unwrap_or(false)
+        && Depfile::load(depfile)
+            .map(|depfile| depfile.is_up_to_date(root))
+            .unwrap_or(false)
+}
+
+fn save_fingerprint(depfile: &Path, fingerprint: &str) -> Result<(), Error> {
+    std::fs::write(depfile.with_extension("stamp"), fingerprint)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frontend_server_compiles_like_bundle() {
+        let mut options = BuildOptions::default();
+        options
+            .dart_defines
+            .insert("API_URL".to_string(), "https://example.com".to_string());
+        options.flavor = Some("staging".to_string());
+        let bundle = options.bundle_args();
+        let frontend_server = options.frontend_server_args();
+
+        // Reloaded widget classes must have the fields of the running ones
+        assert!(bundle.iter().any(|arg| arg == "--track-widget-creation"));
+        assert!(frontend_server
+            .iter()
+            .any(|arg| arg == "--track-widget-creation"));
+
+        let kernel_args: Vec<String> = bundle
+            .iter()
+            .filter(|arg| arg.starts_with("--track-") || arg.starts_with("--dart-define="))
+            .map(|arg| arg.replacen("--dart-define=", "-D", 1))
+            .collect();
+        assert_eq!(kernel_args, frontend_server);
     }
 }
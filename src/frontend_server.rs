@@ -0,0 +1,198 @@
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::subprocess;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// The frontend server in incremental mode, keeps the compiled program in
+/// memory and only recompiles the libraries that changed.
+pub struct FrontendServer {
+    cmd: Command,
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_boundary: u64,
+}
+
+/// Result of a successful compilation.
+#[derive(Clone, Debug)]
+pub struct CompilerOutput {
+    pub dill: PathBuf,
+    pub errors: usize,
+}
+
+impl FrontendServer {
+    pub fn start(
+        host_engine: &Engine,
+        root: &Path,
+        output_dill: &Path,
+        args: &[String],
+    ) -> Result<Self, Error> {
+        let host_engine_dir = host_engine.engine_dir();
+        let mut cmd = Command::new(host_engine.dart()?);
+        cmd.current_dir(root)
+            .arg(
+                host_engine_dir
+                    .join("gen")
+                    .join("frontend_server.dart.snapshot"),
+            )
+            .arg("--sdk-root")
+            .arg(host_engine_dir.join("flutter_patched_sdk"))
+            .arg("--incremental")
+            .arg("--target=flutter")
+            .arg("-Ddart.vm.profile=false")
+            .arg("-Ddart.vm.product=false")
+            .args(args)
+            .arg("--packages")
+            .arg(".packages")
+            .arg("--output-dill")
+            .arg(output_dill)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped());
+        let mut child = cmd
+            .spawn()
+            .map_err(|err| subprocess::spawn_failed("frontend server", &cmd, err))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(Self {
+            cmd,
+            child,
+            stdin,
+            stdout,
+            next_boundary: 0,
+        })
+    }
+
+    /// Compiles the whole program starting at `main`.
+    pub fn compile(&mut self, main: &Path) -> Result<Option<CompilerOutput>, Error> {
+        writeln!(self.stdin, "compile {}", file_uri(main))?;
+        self.read_output()
+    }
+
+    /// Recompiles the program after `invalidated` files changed.
+    pub fn recompile(
+        &mut self,
+        main: &Path,
+        invalidated: &[PathBuf],
+    ) -> Result<Option<CompilerOutput>, Error> {
+        self.next_boundary += 1;
+        let boundary = format!("cargo-flutter-{}", self.next_boundary);
+        writeln!(self.stdin, "recompile {} {}", file_uri(main), boundary)?;
+        for path in invalidated {
+            writeln!(self.stdin, "{}", file_uri(path))?;
+        }
+        writeln!(self.stdin, "{}", boundary)?;
+        self.read_output()
+    }
+
+    /// Makes the last compilation the base of the next incremental one.
+    pub fn accept(&mut self) -> Result<(), Error> {
+        writeln!(self.stdin, "accept")?;
+        Ok(())
+    }
+
+    /// Discards the last compilation.
+    pub fn reject(&mut self) -> Result<(), Error> {
+        writeln!(self.stdin, "reject")?;
+        // The server acknowledges with an empty result.
+        let key = self.read_result()?;
+        while self.read_line()? != key {}
+        Ok(())
+    }
+
+    /// Makes the next compilation output the whole program.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        writeln!(self.stdin, "reset")?;
+        Ok(())
+    }
+
+    /// Error for a compilation that didn't produce any output, the
+    /// diagnostics have already been printed.
+    pub fn failed(&mut self, step: &str) -> Error {
+        let status = self.child.try_wait().ok().flatten();
+        subprocess::failed(step, &self.cmd, status, Vec::new())
+    }
+
+    fn read_line(&mut self) -> Result<String, Error> {
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            let status = self.child.wait().ok();
+            return Err(subprocess::failed(
+                "frontend server",
+                &self.cmd,
+                status,
+                Vec::new(),
+            ));
+        }
+        Ok(line.trim_end().to_string())
+    }
+
+    /// Waits for the start of a response and returns its boundary key.
+    fn read_result(&mut self) -> Result<String, Error> {
+        loop {
+            let line = self.read_line()?;
+            if let Some(key) = line.strip_prefix("result ") {
+                return Ok(key.to_string());
+            }
+        }
+    }
+
+    /// Reads the response to a compile request. Diagnostics are printed.
+    ///
+    /// ```text
+    /// result <boundary>
+    /// <diagnostics>
+    /// <boundary>
+    /// +<added source>
+    /// -<removed source>
+    /// <boundary> <output dill> <error count>
+    /// ```
+    ///
+    /// Older versions omit the list of sources.
+    fn read_output(&mut self) -> Result<Option<CompilerOutput>, Error> {
+        let key = self.read_result()?;
+        let mut diagnostics = true;
+        loop {
+            let line = self.read_line()?;
+            let rest = match line.strip_prefix(key.as_str()) {
+                Some(rest) => rest.trim(),
+                None => {
+                    if diagnostics {
+                        println!("{}", line);
+                    }
+                    continue;
+                }
+            };
+            if rest.is_empty() {
+                if diagnostics {
+                    diagnostics = false;
+                    continue;
+                }
+                return Ok(None);
+            }
+            let mut split = rest.rsplitn(2, ' ');
+            let errors = split.next().and_then(|n| n.parse().ok()).unwrap_or(0);
+            return Ok(split.next().map(|dill| CompilerOutput {
+                dill: PathBuf::from(dill),
+                errors,
+            }));
+        }
+    }
+}
+
+impl Drop for FrontendServer {
+    fn drop(&mut self) {
+        writeln!(self.stdin, "quit").ok();
+        self.child.wait().ok();
+    }
+}
+
+pub fn file_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    if path.starts_with('/') {
+        format!("file://{}", path)
+    } else {
+        format!("file:///{}", path)
+    }
+}
@@ -1,14 +1,37 @@
+mod cache;
 mod cargo;
+mod cargo_args;
 mod config;
+mod depfile;
+mod doctor;
 mod engine;
 mod error;
 mod flutter;
+mod frontend_server;
+mod observatory;
 pub mod package;
+mod process;
+mod subprocess;
+mod target;
+mod template;
 mod unzip;
+mod vmservice;
+mod watch;
 
-pub use crate::cargo::Cargo;
-pub use crate::config::TomlConfig;
+pub use crate::cache::{CacheEntry, EngineCache};
+pub use crate::cargo::{Artifacts, Cargo};
+pub use crate::cargo_args::CargoArgs;
+pub use crate::config::{TomlConfig, TomlMetadata};
+pub use crate::depfile::Depfile;
+pub use crate::doctor::{Check, Doctor};
 pub use crate::engine::{Build, Engine};
 pub use crate::error::Error;
-pub use crate::flutter::Flutter;
+pub use crate::flutter::{BuildOptions, Flutter, FlutterVersion};
+pub use crate::frontend_server::{CompilerOutput, FrontendServer};
+pub use crate::observatory::{free_port, Observatory};
 pub use crate::package::{Item, Package};
+pub use crate::process::{exit_code, AppProcess, Launcher};
+pub use crate::target::{TargetSpec, Targets};
+pub use crate::template::Template;
+pub use crate::vmservice::{FlutterView, VmService};
+pub use crate::watch::{read_stdin, watch_sources, Action};
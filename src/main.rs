@@ -3,11 +3,17 @@ use cargo::ops::{CompileOptions, Packages};
 use cargo::util::Config;
 use cargo_flutter::package::apk::Apk;
 use cargo_flutter::package::appimage::AppImage;
-use cargo_flutter::{Build, Cargo, Engine, Error, Flutter, Item, Package, TomlConfig};
-use clap::{App, AppSettings, Arg, SubCommand};
+use cargo_flutter::{
+    exit_code, free_port, read_stdin, watch_sources, AppProcess, Build, BuildOptions, Cargo,
+    Doctor, Engine, EngineCache, Error, Flutter, Item, Launcher, Package, Template, TomlConfig,
+    TomlMetadata,
+};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use exitfailure::ExitFailure;
-use rand::Rng;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::time::{Duration, SystemTime};
 use std::{env, str};
 
 fn main() -> Result<(), ExitFailure> {
@@ -18,6 +24,7 @@ fn main() -> Result<(), ExitFailure> {
         .subcommand(
             SubCommand::with_name("flutter")
                 .setting(AppSettings::TrailingVarArg)
+                .setting(AppSettings::SubcommandsNegateReqs)
                 .version(env!("CARGO_PKG_VERSION"))
                 .author("flutter-rs")
                 .about("Provides a smooth experience for developing flutter-rs apps.")
@@ -26,6 +33,42 @@ fn main() -> Result<(), ExitFailure> {
                         .long("quiet")
                         .help("avoids excessive printing to stdout"),
                 )
+                .arg(
+                    Arg::with_name("offline")
+                        .long("offline")
+                        .help("Fails instead of accessing the network"),
+                )
+                .arg(
+                    Arg::with_name("flutter-mode")
+                        .long("flutter-mode")
+                        .value_name("MODE")
+                        .takes_value(true)
+                        .possible_values(&["debug", "profile", "release"])
+                        .help("Flutter build mode (defaults to release if `--release` is passed)"),
+                )
+                .arg(
+                    Arg::with_name("profile")
+                        .long("profile")
+                        .conflicts_with("flutter-mode")
+                        .help("Shortcut for `--flutter-mode profile`"),
+                )
+                .arg(
+                    Arg::with_name("local-engine")
+                        .long("local-engine")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .help("Uses a locally built engine from an engine `out` directory"),
+                )
+                .arg(
+                    Arg::with_name("local-engine-host")
+                        .long("local-engine-host")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .help(
+                            "Host build matching a cross compiled `--local-engine` (defaults to \
+                             its `host_<mode>` sibling)",
+                        ),
+                )
                 .arg(
                     Arg::with_name("no-flutter")
                         .long("no-flutter")
@@ -39,7 +82,7 @@ fn main() -> Result<(), ExitFailure> {
                 .arg(
                     Arg::with_name("no-attach")
                         .long("no-attach")
-                        .help("Skips attaching the flutter debugger"),
+                        .help("Skips attaching for hot reload"),
                 )
                 .arg(
                     Arg::with_name("no-aot")
@@ -53,6 +96,55 @@ fn main() -> Result<(), ExitFailure> {
                         .takes_value(true)
                         .help("Dart entrypoint (defaults to `lib/main.dart`)"),
                 )
+                .arg(
+                    Arg::with_name("entrypoint")
+                        .long("entrypoint")
+                        .value_name("NAME")
+                        .takes_value(true)
+                        .conflicts_with("dart-main")
+                        .help(
+                            "Named dart entrypoint from `[package.metadata.flutter.entrypoints]`",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("dart-define")
+                        .long("dart-define")
+                        .value_name("KEY=VALUE")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Compile time constant available via `String.fromEnvironment`"),
+                )
+                .arg(
+                    Arg::with_name("flavor")
+                        .long("flavor")
+                        .value_name("FLAVOR")
+                        .takes_value(true)
+                        .help("Build flavor passed to flutter"),
+                )
+                .arg(
+                    Arg::with_name("tree-shake-icons")
+                        .long("tree-shake-icons")
+                        .help("Removes unused icons from icon fonts"),
+                )
+                .arg(
+                    Arg::with_name("obfuscate")
+                        .long("obfuscate")
+                        .help("Obfuscates dart symbol names in the aot snapshot"),
+                )
+                .arg(
+                    Arg::with_name("split-debug-info")
+                        .long("split-debug-info")
+                        .value_name("DIR")
+                        .takes_value(true)
+                        .help("Writes the aot snapshot's debug symbols to DIR, relative to the build dir"),
+                )
+                .arg(
+                    Arg::with_name("watch")
+                        .long("watch")
+                        .conflicts_with_all(&["profile", "no-flutter", "no-attach", "drive"])
+                        .help("Hot reloads dart changes and relaunches the app on rust changes"),
+                )
                 .arg(
                     Arg::with_name("drive")
                         .long("drive")
@@ -82,6 +174,71 @@ fn main() -> Result<(), ExitFailure> {
                         .takes_value(true)
                         .required(true)
                         .multiple(true),
+                )
+                .subcommand(
+                    SubCommand::with_name("new")
+                        .about("Creates a flutter-rs app in a new directory")
+                        .arg(
+                            Arg::with_name("path")
+                                .value_name("PATH")
+                                .required(true)
+                                .help("Directory of the app"),
+                        )
+                        .args(&template_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("init")
+                        .about("Creates a flutter-rs app in an existing directory")
+                        .arg(
+                            Arg::with_name("path")
+                                .value_name("PATH")
+                                .default_value(".")
+                                .help("Directory of the app"),
+                        )
+                        .args(&template_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("doctor")
+                        .about("Checks the tools cargo-flutter depends on")
+                        .args(&engine_args(true))
+                        .arg(
+                            Arg::with_name("json")
+                                .long("json")
+                                .help("Prints the report as json"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("engine")
+                        .setting(AppSettings::SubcommandRequiredElseHelp)
+                        .about("Manages the flutter engine cache")
+                        .subcommand(SubCommand::with_name("list").about("Lists the cached engines"))
+                        .subcommand(
+                            SubCommand::with_name("path")
+                                .about("Prints the path of an engine")
+                                .args(&engine_args(false)),
+                        )
+                        .subcommand(
+                            SubCommand::with_name("fetch")
+                                .about("Downloads engines ahead of time")
+                                .args(&engine_args(true)),
+                        )
+                        .subcommand(
+                            SubCommand::with_name("remove")
+                                .about("Removes cached engines of a version")
+                                .args(&engine_args(true)),
+                        )
+                        .subcommand(
+                            SubCommand::with_name("gc")
+                                .about("Removes versions that weren't used recently")
+                                .arg(
+                                    Arg::with_name("days")
+                                        .long("days")
+                                        .value_name("DAYS")
+                                        .takes_value(true)
+                                        .default_value("30")
+                                        .help("Keeps versions used within this many days"),
+                                ),
+                        ),
                 ),
         )
         .get_matches();
@@ -92,34 +249,74 @@ fn main() -> Result<(), ExitFailure> {
         return Err(Error::NotCalledWithCargo.into());
     };
 
-    // Setup cargo
     let quiet = matches.is_present("quiet");
+    let offline = matches.is_present("offline");
+    if let Some(matches) = matches.subcommand_matches("engine") {
+        return engine(matches, quiet, offline);
+    }
+    if let Some(matches) = matches.subcommand_matches("doctor") {
+        return doctor(matches, offline);
+    }
+    if let Some(matches) = matches.subcommand_matches("new") {
+        return new(matches, false);
+    }
+    if let Some(matches) = matches.subcommand_matches("init") {
+        return new(matches, true);
+    }
+
+    // Setup cargo
     let cargo_args: Vec<&str> = matches
         .values_of("cargo-args")
         .expect("cargo-args to not be null")
         .collect();
     let mut cargo_config = Config::default()?;
-    let cargo = Cargo::new(&mut cargo_config, cargo_args)?;
-
-    // Parse options
-    let build = if cargo.release() {
-        Build::Release
+    let mode = if matches.is_present("profile") {
+        Some(Build::Profile)
     } else {
-        Build::Debug
+        matches
+            .value_of("flutter-mode")
+            .map(str::parse)
+            .transpose()?
     };
-    let aot = build == Build::Release;
+    let cargo = Cargo::new(&mut cargo_config, cargo_args, offline, mode)?;
+
+    // Parse options
+    let build = cargo.build();
+    let aot = build != Build::Debug;
+    // Reloads need the kernel of a debug build
+    if matches.is_present("watch") && aot {
+        return Err(Error::WatchRequiresDebug.into());
+    }
     let sign = build == Build::Debug && matches.is_present("sign")
         || build == Build::Release && !matches.is_present("no-sign");
-    let config = TomlConfig::load(&cargo).ok();
-    let metadata = config
-        .as_ref()
-        .map(|config| config.metadata())
+
+    // A single package is built like an app, of several packages only the
+    // ones with `[package.metadata.flutter]` are.
+    let packages = cargo.packages()?;
+    let single = packages.len() == 1;
+    let mut configs = Vec::new();
+    for package in packages {
+        // A broken `[package.metadata.flutter]` would build a plain crate
+        let config = TomlConfig::load_package(package)?;
+        if single || config.is_flutter_app() {
+            configs.push((package, config));
+        }
+    }
+    // The engine is shared by the apps, its settings come from the first one
+    let metadata = configs
+        .first()
+        .map(|(_, config)| config.metadata())
         .unwrap_or_default();
 
     // Find flutter sdk
     let flutter = Flutter::new()?;
     log::debug!("FLUTTER_ROOT {}", flutter.root().display());
 
+    // Fail before downloading anything if an app needs another sdk
+    for (_, config) in &configs {
+        config.metadata().check_flutter_version(&flutter)?;
+    }
+
     // Find engine version used by the flutter sdk
     let engine_version = metadata.engine_version().unwrap_or_else(|| {
         std::env::var("FLUTTER_ENGINE_VERSION")
@@ -128,144 +325,739 @@ fn main() -> Result<(), ExitFailure> {
     });
     log::debug!("FLUTTER_ENGINE_VERSION {}", engine_version);
 
-    // Download host engine
+    // Find where to download the engine from
+    let engine_url = engine_url(&metadata);
+    log::debug!("FLUTTER_ENGINE_MIRROR {:?}", engine_url);
+
+    let targets = metadata.targets();
     let host_triple = cargo.host_triple()?;
-    let host_engine = Engine::new(engine_version.clone(), host_triple, build);
-    host_engine.download(quiet)?;
+    let mut host_engine = Engine::new(engine_version.clone(), host_triple.clone(), build);
+    host_engine.set_target_spec(targets.get(&host_triple)?.clone());
+    if let Some(engine_url) = &engine_url {
+        host_engine.set_url_template(engine_url.clone());
+    }
 
-    // Download target engine
     let target_triple = cargo.target_triple()?;
-    let target_engine = Engine::new(engine_version, target_triple.clone(), build);
-    target_engine.download(quiet)?;
+    let target_spec = targets.get(&target_triple)?.clone();
+    let mut target_engine = Engine::new(engine_version, target_triple.clone(), build);
+    target_engine.set_target_spec(target_spec.clone());
+    if let Some(engine_url) = engine_url {
+        target_engine.set_url_template(engine_url);
+    }
+
+    // Use a locally built engine
+    let local_engine = match matches.value_of("local-engine") {
+        Some(path) => Some(env::current_dir()?.join(path)),
+        None => metadata.local_engine_path().and_then(|path| {
+            configs
+                .first()
+                .map(|(package, _)| package.root().join(path))
+        }),
+    };
+    if let Some(local_engine) = local_engine {
+        log::debug!("FLUTTER_LOCAL_ENGINE {}", local_engine.display());
+        // The dart sdk and frontend server only exist in host builds
+        let local_host_engine = if target_triple == host_triple {
+            local_engine.clone()
+        } else {
+            match matches.value_of("local-engine-host") {
+                Some(path) => env::current_dir()?.join(path),
+                None => local_host_engine(&local_engine, build),
+            }
+        };
+        log::debug!("FLUTTER_LOCAL_HOST_ENGINE {}", local_host_engine.display());
+        host_engine
+            .set_local_path(local_host_engine)
+            .map_err(|err| match err {
+                Error::LocalEngineNotFound(path) if target_triple != host_triple => {
+                    Error::LocalHostEngineNotFound(path)
+                }
+                err => err,
+            })?;
+        target_engine.set_local_path(local_engine)?;
+    }
+
+    if cargo.offline() {
+        Engine::ensure_cached(&[&host_engine, &target_engine])?;
+    }
+
+    // Download host engine
+    host_engine.download(
+        quiet,
+        metadata.engine_sha256(&host_engine.platform()?).as_deref(),
+    )?;
+
+    // Download target engine
+    target_engine.download(
+        quiet,
+        metadata
+            .engine_sha256(&target_engine.platform()?)
+            .as_deref(),
+    )?;
+    host_engine.mark_used()?;
+    target_engine.mark_used()?;
 
-    //
-    let flutter_asset_dir = cargo.build_dir().join("flutter_assets");
-    let snapshot_path = cargo.build_dir().join("app.so");
     let engine_path = cargo
         .build_dir()
         .join("deps")
-        .join(target_engine.library_name());
-    let dart_main = PathBuf::from(matches.value_of("dart-main").unwrap_or("lib/main.dart"));
-    log::debug!("FLUTTER_ASSET_DIR {}", flutter_asset_dir.display());
+        .join(target_spec.library_name());
 
-    // Copy target engine to deps dir
-    if !engine_path.exists() {
+    // Copy target engine to deps dir, local engines may have been rebuilt
+    if !engine_path.exists() || target_engine.is_local() {
         std::fs::create_dir_all(engine_path.parent().unwrap())?;
-        std::fs::copy(target_engine.engine_path(), &engine_path)?;
-
-        if target_triple == "x86_64-pc-windows-msvc" {
-            let from_dir = target_engine.engine_path().parent().unwrap().to_owned();
-            let to_dir = engine_path.parent().unwrap();
-            for file in &[
-                "flutter_engine.lib",
-                "flutter_engine.exp",
-                "flutter_engine.pdb",
-            ] {
-                std::fs::copy(from_dir.join(file), to_dir.join(file))?;
-            }
+        std::fs::copy(target_engine.engine_path()?, &engine_path)?;
+
+        let from_dir = target_engine.engine_dir();
+        let to_dir = engine_path.parent().unwrap();
+        for file in target_spec.sidecar_names() {
+            std::fs::copy(from_dir.join(file), to_dir.join(file))?;
         }
     }
 
+    // Apps of a workspace with several of them are built into their own dir
+    let workspace_apps = cargo
+        .workspace()
+        .members()
+        .filter(|member| {
+            TomlConfig::load_package(member)
+                .map(|config| config.is_flutter_app())
+                .unwrap_or_default()
+        })
+        .count();
+    let apps = configs
+        .into_iter()
+        .map(|(package, config)| {
+            FlutterApp::new(matches, &cargo, package, config, workspace_apps > 1)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
     // Build flutter_assets and aot binary
-    if config.is_some() {
+    for app in &apps {
+        log::debug!("FLUTTER_ASSET_DIR {}", app.asset_dir().display());
         if !matches.is_present("no-flutter") && !matches.is_present("no-bundle") {
-            println!("flutter build bundle {}", dart_main.display());
-            flutter.bundle(&cargo, build, &dart_main)?;
+            println!("flutter build bundle {}", app.dart_main.display());
+            flutter.bundle(
+                &cargo,
+                &app.root,
+                build,
+                &app.dart_main,
+                &app.flutter_dir,
+                &app.build_options,
+            )?;
         }
 
         if !matches.is_present("no-flutter") && !matches.is_present("no-aot") && aot {
-            flutter.aot(&cargo, &host_engine, &target_engine)?;
+            std::fs::create_dir_all(&app.flutter_dir)?;
+            flutter.aot(
+                &app.root,
+                &host_engine,
+                &target_engine,
+                &app.dart_main,
+                &app.flutter_dir,
+                &app.build_options,
+            )?;
+
+            // Release builds ship the same assets for every entrypoint
+            if cargo.cmd() == "build" {
+                for (name, dart_main) in &app.entrypoints {
+                    let flutter_dir = app.flutter_dir.join("entrypoints").join(name);
+                    let mut build_options = app.build_options.clone();
+                    build_options.split_debug_info =
+                        build_options.split_debug_info.map(|dir| dir.join(name));
+                    std::fs::create_dir_all(&flutter_dir)?;
+                    flutter.aot(
+                        &app.root,
+                        &host_engine,
+                        &target_engine,
+                        dart_main,
+                        &flutter_dir,
+                        &build_options,
+                    )?;
+                }
+            }
         }
     }
 
-    match (cargo.cmd(), config) {
-        ("build", Some(config)) => {
-            let mut package = Package::new(&config.package.name);
-            package.add_lib(engine_path);
-            if aot {
-                package.add_lib(snapshot_path);
+    match cargo.cmd() {
+        "build" if !apps.is_empty() => {
+            if let Some(format) = matches.value_of("format") {
+                if !target_spec.supports_format(format) {
+                    return Err(Error::FormatNotSupported.into());
+                }
             }
-            package.add_asset(flutter_asset_dir);
 
-            if !target_triple.contains("android") {
-                cargo.exec()?;
-                package.add_bin(cargo.build_dir().join(&config.package.name));
+            if !target_spec.is_android() {
+                let mut artifacts = cargo.exec_artifacts()?;
+                // The cdylibs of the other members may be loaded by any app
+                let shared_libs: Vec<PathBuf> = artifacts
+                    .iter()
+                    .filter(|(name, _)| !apps.iter().any(|app| app.name() == name.as_str()))
+                    .flat_map(|(_, artifacts)| artifacts.libs.clone())
+                    .collect();
+                for app in &apps {
+                    let mut package = app.package(&engine_path, aot);
+                    let artifacts = artifacts.remove(app.name()).unwrap_or_default();
+                    for bin in artifacts.bins {
+                        package.add_bin(bin);
+                    }
+                    for lib in artifacts.libs.into_iter().chain(shared_libs.clone()) {
+                        package.add_lib(lib);
+                    }
 
-                if let Some(format) = matches.value_of("format") {
-                    match format {
-                        "appimage" => {
-                            let builder = AppImage::new(metadata.appimage.unwrap_or_default());
-                            builder.build(&cargo, &package, sign)?;
-                        }
-                        "dmg" => {}
-                        "lipo" => {}
-                        "nsis" => {}
-                        _ => return Err(Error::FormatNotSupported.into()),
+                    if let Some("appimage") = matches.value_of("format") {
+                        let builder = AppImage::new(
+                            app.metadata().appimage.unwrap_or_default(),
+                            target_spec.arch_name().map(String::from),
+                            app.root.clone(),
+                        );
+                        builder.build(&cargo, &package, sign)?;
                     }
                 }
             } else {
                 use lib_cargo_apk::config::AndroidBuildTarget;
-                let mut android_config = lib_cargo_apk::config::load(cargo.package()?).unwrap();
-                let target = match target_triple.as_str() {
-                    "armv7-linux-androideabi" => AndroidBuildTarget::ArmV7a,
-                    "aarch64-linux-android" => AndroidBuildTarget::Arm64V8a,
-                    "i686-linux-android" => AndroidBuildTarget::X86,
-                    "x86_64-linux-android" => AndroidBuildTarget::X86_64,
-                    _ => panic!("unsupported android target"),
-                };
-                android_config.build_targets = vec![target];
-                android_config.release = build != Build::Debug;
+                for app in &apps {
+                    let mut package = app.package(&engine_path, aot);
+                    let mut android_config =
+                        lib_cargo_apk::config::load(app.cargo_package).unwrap();
+                    // `AndroidBuildTarget` deserializes from the target triple
+                    let target: AndroidBuildTarget =
+                        toml::Value::String(target_triple.clone()).try_into()?;
+                    android_config.build_targets = vec![target];
+                    android_config.release = build != Build::Debug;
 
-                let mut options =
-                    CompileOptions::new(cargo.workspace().config(), CompileMode::Build)?;
-                options.build_config.profile_kind = if build == Build::Debug {
-                    ProfileKind::Dev
-                } else {
-                    ProfileKind::Release
-                };
-                options.spec = if let Ok(package) = cargo.package() {
-                    Packages::Packages(vec![package.name().to_string()])
-                } else {
-                    Packages::Default
-                };
+                    let mut options =
+                        CompileOptions::new(cargo.workspace().config(), CompileMode::Build)?;
+                    options.build_config.profile_kind = if build == Build::Debug {
+                        ProfileKind::Dev
+                    } else {
+                        ProfileKind::Release
+                    };
+                    options.features = cargo
+                        .args()
+                        .features
+                        .iter()
+                        .map(|feature| feature.to_string())
+                        .collect();
+                    options.all_features = cargo.args().all_features;
+                    options.no_default_features = cargo.args().no_default_features;
+                    options.spec = Packages::Packages(vec![app.name().to_string()]);
 
-                let libs = lib_cargo_apk::build_shared_libraries(
-                    cargo.workspace(),
-                    &android_config,
-                    options,
-                    &cargo.build_dir(),
-                )?;
-                for (_, libs) in libs.shared_libraries.iter_all() {
-                    for lib in libs {
-                        package.add_lib(Item::new(lib.path.clone(), lib.filename.clone()));
+                    let libs = lib_cargo_apk::build_shared_libraries(
+                        cargo.workspace(),
+                        &android_config,
+                        options,
+                        &cargo.build_dir(),
+                    )?;
+                    for (_, libs) in libs.shared_libraries.iter_all() {
+                        for lib in libs {
+                            package.add_lib(Item::new(lib.path.clone(), lib.filename.clone()));
+                        }
                     }
-                }
-                if let Some(format) = matches.value_of("format") {
-                    if format != "apk" {
-                        return Err(Error::FormatNotSupported.into());
+                    if matches.is_present("format") {
+                        let builder = Apk::new(android_config);
+                        builder.build(&cargo, &package, sign)?;
                     }
-                    let builder = Apk::new(android_config);
-                    builder.build(&cargo, &package, sign)?;
                 }
             }
         }
-        ("run", Some(_config)) => {
-            let mut rng = rand::thread_rng();
-            let port = rng.gen_range(1024, 49152);
-            let observatory = format!("http://127.0.0.1:{}", port);
-            std::env::set_var("FLUTTER_AOT_SNAPSHOT", &snapshot_path);
-            std::env::set_var("FLUTTER_ASSET_DIR", &flutter_asset_dir);
+        "run" if !apps.is_empty() => {
+            // Cargo runs a single package
+            let app = &apps[0];
+            let port = free_port()?;
+            std::env::set_var("FLUTTER_AOT_SNAPSHOT", app.flutter_dir.join("app.so"));
+            std::env::set_var("FLUTTER_ASSET_DIR", app.asset_dir());
             std::env::set_var("DART_OBSERVATORY_PORT", port.to_string());
-            cargo.spawn()?;
+            let attach =
+                !matches.is_present("no-flutter") && !matches.is_present("no-attach") && !aot;
+            if !matches.is_present("drive") && !attach {
+                let mut app = AppProcess::new(cargo.spawn()?)?;
+                std::process::exit(exit_code(app.finish()?));
+            }
+
+            let mut launcher = match Launcher::launch(&cargo) {
+                Ok(launcher) => launcher,
+                Err(Error::AppExited(code)) => std::process::exit(code),
+                Err(err) => return Err(err.into()),
+            };
+            log::debug!("DART_OBSERVATORY_URI {}", launcher.uri());
+            let result = if matches.is_present("drive") {
+                let uri = launcher.uri().to_string();
+                flutter.drive(&host_engine, &app.root, &uri, &app.dart_main)
+            } else {
+                let (sender, actions) = channel();
+                read_stdin(sender.clone());
+                // Sources are watched until the session ends
+                let _watcher = if matches.is_present("watch") {
+                    Some(watch_sources(&cargo, &app.root, sender)?)
+                } else {
+                    None
+                };
+                flutter.attach(
+                    &app.root,
+                    &host_engine,
+                    &app.dart_main,
+                    &app.flutter_dir,
+                    &app.build_options,
+                    &mut launcher,
+                    actions,
+                )
+            };
 
-            if matches.is_present("drive") {
-                flutter.drive(&host_engine, &cargo, &observatory, &dart_main)?;
-            } else if !matches.is_present("no-flutter") && !matches.is_present("no-attach") {
-                flutter.attach(&cargo, &observatory)?;
+            // Either the app exited and ended the session or the session
+            // ended and takes the app down with it.
+            if launcher.app().try_wait()?.is_some() {
+                std::process::exit(exit_code(launcher.app().finish()?));
             }
+            launcher.app().kill()?;
+            result?;
         }
         _ => cargo.exec()?,
     }
 
     Ok(())
 }
+
+/// A workspace member built as a flutter app.
+struct FlutterApp<'a> {
+    cargo_package: &'a cargo::core::Package,
+    config: TomlConfig,
+    /// Directory of the dart project, the member's own if it has a
+    /// `pubspec.yaml` and the workspace root otherwise.
+    root: PathBuf,
+    dart_main: PathBuf,
+    flutter_dir: PathBuf,
+    build_options: BuildOptions,
+    /// Named entrypoints compiled next to the default one when none was
+    /// selected, each into `entrypoints/<name>/app.so`.
+    entrypoints: BTreeMap<String, PathBuf>,
+}
+
+impl<'a> FlutterApp<'a> {
+    fn new(
+        matches: &ArgMatches,
+        cargo: &Cargo,
+        cargo_package: &'a cargo::core::Package,
+        config: TomlConfig,
+        own_dir: bool,
+    ) -> Result<Self, Error> {
+        let metadata = config.metadata();
+        let root = if cargo_package.root().join("pubspec.yaml").exists() {
+            cargo_package.root().to_path_buf()
+        } else {
+            cargo.workspace().root().to_path_buf()
+        };
+        let app_dir = if own_dir {
+            cargo
+                .build_dir()
+                .join("apps")
+                .join(config.package.name.as_str())
+        } else {
+            cargo.build_dir()
+        };
+
+        // Named entrypoints get their own output dir so they don't overwrite each other
+        let (dart_main, flutter_dir) = match matches.value_of("entrypoint") {
+            Some(name) => (
+                PathBuf::from(metadata.entrypoint(name)?),
+                app_dir.join("entrypoints").join(name),
+            ),
+            None => (
+                PathBuf::from(matches.value_of("dart-main").unwrap_or("lib/main.dart")),
+                app_dir,
+            ),
+        };
+
+        // Command line options take precedence over `Cargo.toml`
+        let mut build_options = metadata.build_options();
+        for define in matches.values_of("dart-define").into_iter().flatten() {
+            let mut split = define.splitn(2, '=');
+            let key = split.next().unwrap_or_default();
+            let value = split
+                .next()
+                .ok_or_else(|| Error::InvalidDefine(define.to_string()))?;
+            build_options
+                .dart_defines
+                .insert(key.to_string(), value.to_string());
+        }
+        if let Some(flavor) = matches.value_of("flavor") {
+            build_options.flavor = Some(flavor.to_string());
+        }
+        if matches.is_present("tree-shake-icons") {
+            build_options.tree_shake_icons = true;
+        }
+        if matches.is_present("obfuscate") {
+            build_options.obfuscate = true;
+        }
+        if let Some(dir) = matches.value_of("split-debug-info") {
+            build_options.split_debug_info = Some(PathBuf::from(dir));
+        }
+        // Obfuscated stack traces can't be symbolized without the debug info
+        if build_options.obfuscate && build_options.split_debug_info.is_none() {
+            build_options.split_debug_info = Some(PathBuf::from("symbols"));
+        }
+        // Symbols are kept per version outside of the packaged files
+        if let Some(dir) = build_options.split_debug_info.take() {
+            let mut dir = cargo
+                .build_dir()
+                .join(dir)
+                .join(cargo_package.version().to_string());
+            if own_dir {
+                dir = dir.join(config.package.name.as_str());
+            }
+            if let Some(name) = matches.value_of("entrypoint") {
+                dir = dir.join(name);
+            }
+            build_options.split_debug_info = Some(dir);
+        }
+
+        let entrypoints = if matches.is_present("entrypoint") || matches.is_present("dart-main") {
+            BTreeMap::new()
+        } else {
+            metadata
+                .entrypoints()
+                .into_iter()
+                .map(|(name, dart_main)| (name, PathBuf::from(dart_main)))
+                .collect()
+        };
+
+        Ok(Self {
+            cargo_package,
+            config,
+            root,
+            dart_main,
+            flutter_dir,
+            build_options,
+            entrypoints,
+        })
+    }
+
+    fn name(&self) -> &str {
+        &self.config.package.name
+    }
+
+    fn metadata(&self) -> TomlMetadata {
+        self.config.metadata()
+    }
+
+    fn asset_dir(&self) -> PathBuf {
+        self.flutter_dir.join("flutter_assets")
+    }
+
+    /// The package of the app with the engine and the flutter assets.
+    fn package(&self, engine_path: &Path, aot: bool) -> Package {
+        let mut package = Package::new(self.name());
+        package.add_lib(engine_path.to_path_buf());
+        if aot {
+            package.add_lib(self.flutter_dir.join("app.so"));
+        }
+        package.add_asset(self.asset_dir());
+        package
+    }
+}
+
+/// `FLUTTER_ENGINE_MIRROR` overrides the manifest, so build machines can
+/// redirect projects that pin a url.
+fn engine_url(metadata: &TomlMetadata) -> Option<String> {
+    std::env::var("FLUTTER_ENGINE_MIRROR")
+        .ok()
+        .or_else(|| metadata.engine_url())
+}
+
+/// The `host_<mode>` build next to the engine `out` directory `target`, for
+/// example `out/host_debug_unopt` for `out/android_debug_unopt_arm64`.
+fn local_host_engine(target: &Path, build: Build) -> PathBuf {
+    let unopt = target
+        .file_name()
+        .map(|name| name.to_string_lossy().contains("_unopt"))
+        .unwrap_or_default();
+    let name = format!(
+        "host_{}{}",
+        match build {
+            Build::Debug => "debug",
+            Build::Release => "release",
+            Build::Profile => "profile",
+        },
+        if unopt { "_unopt" } else { "" }
+    );
+    target.with_file_name(name)
+}
+
+fn template_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("name")
+            .long("name")
+            .value_name("NAME")
+            .takes_value(true)
+            .help("Package name (defaults to the directory name)"),
+        Arg::with_name("template")
+            .long("template")
+            .value_name("TEMPLATE")
+            .takes_value(true)
+            .possible_values(&["minimal", "plugins", "driver"])
+            .default_value("driver")
+            .help("Project template"),
+    ]
+}
+
+/// Creates an app from a template, `init` allows the directory to exist.
+fn new(matches: &ArgMatches, init: bool) -> Result<(), ExitFailure> {
+    let dir = env::current_dir()?.join(matches.value_of("path").unwrap());
+    if !init && dir.exists() {
+        return Err(Error::FileExists(dir).into());
+    }
+    let name = match matches.value_of("name") {
+        Some(name) => name.to_string(),
+        None => dir
+            .canonicalize()
+            .unwrap_or_else(|_| dir.clone())
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+    let template: Template = matches.value_of("template").unwrap().parse()?;
+    template.generate(&dir, &name)?;
+    println!("Created flutter-rs app `{}` in {}", name, dir.display());
+    Ok(())
+}
+
+fn engine_args<'a, 'b>(multiple: bool) -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("engine-version")
+            .long("engine-version")
+            .value_name("VERSION")
+            .takes_value(true)
+            .help("Engine version (defaults to the one used by the flutter sdk)"),
+        Arg::with_name("target")
+            .long("target")
+            .value_name("TRIPLE")
+            .takes_value(true)
+            .multiple(multiple)
+            .number_of_values(1)
+            .help("Target triple (defaults to the host)"),
+        Arg::with_name("build")
+            .long("build")
+            .value_name("BUILD")
+            .takes_value(true)
+            .multiple(multiple)
+            .number_of_values(1)
+            .possible_values(&["debug", "release", "profile"])
+            .help("Engine build (defaults to debug)"),
+    ]
+}
+
+fn engine(matches: &ArgMatches, quiet: bool, offline: bool) -> Result<(), ExitFailure> {
+    let cache = EngineCache::new();
+    let (cmd, matches) = matches.subcommand();
+    let matches = matches.expect("engine subcommand");
+
+    if cmd == "list" {
+        let entries = cache.entries()?;
+        if entries.is_empty() {
+            println!("No engines in {}", cache.root().display());
+        }
+        for entry in entries {
+            let engine = entry.engine();
+            println!(
+                "{:<42} {:<28} {:<12} {:>10}  {}{}",
+                engine.version(),
+                engine.target(),
+                engine.build().build(),
+                format_size(entry.size()),
+                format_last_used(entry.last_used()),
+                if entry.is_complete() {
+                    ""
+                } else {
+                    " (incomplete)"
+                },
+            );
+        }
+        return Ok(());
+    }
+
+    if cmd == "gc" {
+        let days: u64 = matches.value_of("days").unwrap().parse()?;
+        for version in cache.gc(Duration::from_secs(days * 24 * 60 * 60))? {
+            println!("Removed {}", version);
+        }
+        return Ok(());
+    }
+
+    let mut cargo_config = Config::default()?;
+    let host_triple = cargo_config.load_global_rustc(None)?.host.to_string();
+    let metadata = Cargo::new(&mut cargo_config, vec![], offline, None)
+        .ok()
+        .and_then(|cargo| TomlConfig::load(&cargo).ok())
+        .map(|config| config.metadata())
+        .unwrap_or_default();
+    let version = match matches.value_of("engine-version") {
+        Some(version) => version.to_string(),
+        None => match metadata
+            .engine_version()
+            .or_else(|| env::var("FLUTTER_ENGINE_VERSION").ok())
+        {
+            Some(version) => version,
+            None => Flutter::new()?.engine_version()?,
+        },
+    };
+    let targets: Vec<String> = matches
+        .values_of("target")
+        .map(|targets| targets.map(String::from).collect())
+        .unwrap_or_else(|| vec![host_triple]);
+    let builds = matches
+        .values_of("build")
+        .map(|builds| builds.map(str::parse).collect())
+        .unwrap_or_else(|| Ok(vec![Build::Debug]))?;
+    let specs = metadata.targets();
+    let mut engines = Vec::new();
+    for target in &targets {
+        for build in &builds {
+            let mut engine = Engine::new(version.clone(), target.clone(), *build);
+            if let Ok(spec) = specs.get(target) {
+                engine.set_target_spec(spec.clone());
+            }
+            if let Some(engine_url) = engine_url(&metadata) {
+                engine.set_url_template(engine_url);
+            }
+            engines.push(engine);
+        }
+    }
+
+    match cmd {
+        "path" => {
+            for engine in &engines {
+                println!("{}", engine.engine_dir().display());
+            }
+        }
+        "fetch" => {
+            if offline {
+                Engine::ensure_cached(&engines.iter().collect::<Vec<_>>())?;
+            }
+            for engine in &engines {
+                engine.download(
+                    quiet,
+                    metadata.engine_sha256(&engine.platform()?).as_deref(),
+                )?;
+            }
+        }
+        "remove" => {
+            // Without filters every target and build of the version is removed.
+            for entry in cache.entries()? {
+                let engine = entry.engine();
+                if engine.version() == version
+                    && (!matches.is_present("target")
+                        || targets.iter().any(|t| t == engine.target()))
+                    && (!matches.is_present("build") || builds.contains(&engine.build()))
+                {
+                    cache.remove(engine)?;
+                    println!("Removed {}", engine.engine_dir().display());
+                }
+            }
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+fn doctor(matches: &ArgMatches, offline: bool) -> Result<(), ExitFailure> {
+    let mut cargo_config = Config::default()?;
+    let host_triple = cargo_config.load_global_rustc(None)?.host.to_string();
+    let metadata = Cargo::new(&mut cargo_config, vec![], offline, None)
+        .ok()
+        .and_then(|cargo| TomlConfig::load(&cargo).ok())
+        .map(|config| config.metadata())
+        .unwrap_or_default();
+
+    let mut doctor = Doctor::new();
+    let sdk_version = doctor.flutter();
+    doctor.flutter_version(&metadata);
+    let pinned = matches
+        .value_of("engine-version")
+        .map(String::from)
+        .or_else(|| metadata.engine_version())
+        .or_else(|| env::var("FLUTTER_ENGINE_VERSION").ok());
+    doctor.engine_version(sdk_version.as_deref(), pinned.as_deref());
+    let version = pinned.or(sdk_version);
+
+    let targets: Vec<String> = matches
+        .values_of("target")
+        .map(|targets| targets.map(String::from).collect())
+        .unwrap_or_else(|| vec![host_triple.clone()]);
+    let builds = matches
+        .values_of("build")
+        .map(|builds| builds.map(str::parse).collect())
+        .unwrap_or_else(|| Ok(vec![Build::Debug]))?;
+    let specs = metadata.targets();
+    let mut target_specs = Vec::new();
+    for target in &targets {
+        target_specs.extend(doctor.target(&specs, target).cloned());
+    }
+
+    // Cross compiling uses the host engine as well
+    let mut engines = Vec::new();
+    if let Some(version) = &version {
+        let mut triples = vec![host_triple];
+        for spec in &target_specs {
+            if !triples.iter().any(|triple| triple == spec.triple()) {
+                triples.push(spec.triple().to_string());
+            }
+        }
+        for triple in &triples {
+            for build in &builds {
+                let mut engine = Engine::new(version.clone(), triple.clone(), *build);
+                if let Ok(spec) = specs.get(triple) {
+                    engine.set_target_spec(spec.clone());
+                }
+                if let Some(engine_url) = engine_url(&metadata) {
+                    engine.set_url_template(engine_url);
+                }
+                engines.push(engine);
+            }
+        }
+    }
+    doctor.engines(&engines);
+    let triples: Vec<String> = target_specs
+        .iter()
+        .map(|spec| spec.triple().to_string())
+        .collect();
+    doctor.rust_targets(&triples);
+    for spec in &target_specs {
+        doctor.packaging_tools(spec);
+    }
+    if target_specs.iter().any(|spec| spec.is_android()) {
+        doctor.android();
+    }
+
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(doctor.checks())?);
+    } else {
+        doctor.print();
+    }
+    if !doctor.is_ok() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in &["B", "KiB", "MiB"] {
+        if size < 1024.0 {
+            return format!("{:.1} {}", size, unit);
+        }
+        size /= 1024.0;
+    }
+    format!("{:.1} GiB", size)
+}
+
+fn format_last_used(last_used: Option<SystemTime>) -> String {
+    let age = match last_used.and_then(|time| SystemTime::now().duration_since(time).ok()) {
+        Some(age) => age.as_secs(),
+        None => return "never used".to_string(),
+    };
+    match age {
+        0..=59 => "used just now".to_string(),
+        60..=3599 => format!("used {} minutes ago", age / 60),
+        3600..=86399 => format!("used {} hours ago", age / 3600),
+        _ => format!("used {} days ago", age / 86400),
+    }
+}
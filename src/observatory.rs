@@ -0,0 +1,121 @@
+use crate::error::Error;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::process::Child;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Messages the engine prints once the observatory is up, older engines
+/// use the first one.
+const LISTENING: &[&str] = &[
+    "Observatory listening on ",
+    "The Dart VM service is listening on ",
+];
+
+/// Printed by cargo right before it starts the app.
+const RUNNING: &str = "Running `";
+
+/// Returns a port that nothing is listening on.
+pub fn free_port() -> Result<u16, Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.port())
+}
+
+enum Event {
+    Running,
+    Listening(String),
+}
+
+/// Forwards the output of `cargo run` and picks up the uri the observatory
+/// of the app is listening on.
+pub struct Observatory {
+    events: Receiver<Event>,
+    forwarders: Vec<JoinHandle<()>>,
+}
+
+impl Observatory {
+    /// Takes over the piped stdout and stderr of `child`.
+    pub fn watch(child: &mut Child) -> Self {
+        let (sender, events) = channel();
+        let mut forwarders = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            forwarders.push(forward(stdout, std::io::stdout(), sender.clone()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            forwarders.push(forward(stderr, std::io::stderr(), sender));
+        }
+        Self { events, forwarders }
+    }
+
+    /// Waits until the output has been forwarded, which is once every
+    /// process writing to the pipes exited.
+    pub fn join(&mut self) {
+        for forwarder in self.forwarders.drain(..) {
+            forwarder.join().ok();
+        }
+    }
+
+    /// Waits for the observatory uri, including its auth code. Building the
+    /// app doesn't count towards `timeout`.
+    pub fn uri(&self, timeout: Duration) -> Result<String, Error> {
+        self.wait(timeout, None)
+    }
+
+    /// Waits at most `timeout` for the observatory uri, for when cargo
+    /// doesn't announce the app because it runs with `--quiet`.
+    pub fn uri_within(&self, timeout: Duration) -> Result<String, Error> {
+        self.wait(timeout, Some(Instant::now() + timeout))
+    }
+
+    fn wait(&self, timeout: Duration, mut deadline: Option<Instant>) -> Result<String, Error> {
+        loop {
+            let event = match deadline {
+                None => self
+                    .events
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected),
+                Some(deadline) => self
+                    .events
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            };
+            match event {
+                Ok(Event::Running) => deadline = Some(Instant::now() + timeout),
+                Ok(Event::Listening(uri)) => return Ok(uri),
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(Error::ObservatoryTimeout(timeout.as_secs()))
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(Error::ObservatoryNotFound),
+            }
+        }
+    }
+}
+
+fn forward<R, W>(from: R, mut to: W, events: Sender<Event>) -> JoinHandle<()>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let mut from = BufReader::new(from);
+        let mut line = Vec::new();
+        while let Ok(n) = from.read_until(b'\n', &mut line) {
+            if n == 0 {
+                break;
+            }
+            to.write_all(&line).ok();
+            to.flush().ok();
+            let text = String::from_utf8_lossy(&line);
+            if text.trim_start().starts_with(RUNNING) {
+                events.send(Event::Running).ok();
+            }
+            for prefix in LISTENING {
+                if let Some(uri) = text.split(prefix).nth(1) {
+                    let uri = uri.split_whitespace().next().unwrap_or_default();
+                    events.send(Event::Listening(uri.to_string())).ok();
+                }
+            }
+            line.clear();
+        }
+    })
+}
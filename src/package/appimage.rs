@@ -1,5 +1,6 @@
 use crate::cargo::Cargo;
 use crate::package::Package;
+use crate::subprocess;
 use failure::Error;
 use serde::Deserialize;
 use std::fs::Permissions;
@@ -16,11 +17,15 @@ pub struct TomlAppImage {
 
 pub struct AppImage {
     toml: TomlAppImage,
+    arch: Option<String>,
+    root: PathBuf,
 }
 
 impl AppImage {
-    pub fn new(toml: TomlAppImage) -> Self {
-        Self { toml }
+    /// `root` is the directory of the app, the icon is looked up relative
+    /// to it.
+    pub fn new(toml: TomlAppImage, arch: Option<String>, root: PathBuf) -> Self {
+        Self { toml, arch, root }
     }
 
     #[cfg(not(unix))]
@@ -31,15 +36,26 @@ impl AppImage {
     #[cfg(unix)]
     pub fn build(&self, cargo: &Cargo, package: &Package, sign: bool) -> Result<(), Error> {
         let build_dir = cargo.build_dir();
-        let appimage_dir = build_dir.join("appimage");
+        // Apps of a workspace are packaged side by side
+        let appimage_dir = build_dir.join("appimage").join(&package.name);
         let name = self.toml.name.as_ref().unwrap_or(&package.name);
-        let exec = &package.name;
+        // `AppRun` launches the executable named in the `.desktop` file
+        let selected = cargo.args().bin.or(cargo.args().example);
+        let bins = package.bins();
+        let exec = match selected.and_then(|s| bins.iter().find(|bin| bin.name() == s)) {
+            Some(bin) => bin.name(),
+            None if bins.len() == 1 => bins[0].name(),
+            None => {
+                let names = bins.iter().map(|bin| bin.name().to_string()).collect();
+                return Err(crate::error::Error::BinNotSelected(names).into());
+            }
+        };
         let icon_path = self
             .toml
             .icon
             .as_ref()
-            .map(PathBuf::from)
-            .unwrap_or_else(|| cargo.workspace().root().join("assets").join("icon.svg"));
+            .map(|icon| self.root.join(icon))
+            .unwrap_or_else(|| self.root.join("assets").join("icon.svg"));
         if !icon_path.exists() {
             return Err(failure::format_err!(
                 "Icon not found {}",
@@ -87,11 +103,15 @@ impl AppImage {
         let appimagetool = which::which("appimagetool")
             .or_else(|_| Err(failure::format_err!("appimagetool not found")))?;
         let mut cmd = Command::new(appimagetool);
-        cmd.current_dir(&build_dir).arg("appimage");
+        cmd.current_dir(&build_dir).arg(&appimage_dir);
+        // appimagetool can't detect the architecture of cross compiled binaries
+        if let Some(arch) = &self.arch {
+            cmd.env("ARCH", arch);
+        }
         if sign {
             cmd.arg("--sign");
         }
-        cmd.status().expect("Success");
+        subprocess::run("appimagetool", &mut cmd)?;
 
         Ok(())
     }
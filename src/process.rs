@@ -0,0 +1,183 @@
+use crate::cargo::Cargo;
+use crate::error::Error;
+use crate::observatory::Observatory;
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long to wait for the observatory once the app started.
+const OBSERVATORY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The app started by `cargo run`. Signals sent to cargo-flutter are
+/// forwarded to it and it is killed when dropped. Its piped output is
+/// forwarded by an `Observatory`.
+///
+/// On unix `cargo run` replaces itself with the app, on windows killing the
+/// child only stops cargo.
+pub struct AppProcess {
+    child: Child,
+    observatory: Observatory,
+    running: Arc<AtomicBool>,
+    status: Option<ExitStatus>,
+    /// Closed when the app is dropped, which stops forwarding signals.
+    #[cfg(unix)]
+    signals: signal_hook::iterator::Signals,
+}
+
+impl AppProcess {
+    pub fn new(mut child: Child) -> Result<Self, Error> {
+        let observatory = Observatory::watch(&mut child);
+        let running = Arc::new(AtomicBool::new(true));
+        #[cfg(unix)]
+        let signals = forward_signals(child.id() as libc::pid_t, running.clone())?;
+        Ok(Self {
+            child,
+            observatory,
+            running,
+            status: None,
+            #[cfg(unix)]
+            signals,
+        })
+    }
+
+    pub fn observatory(&self) -> &Observatory {
+        &self.observatory
+    }
+
+    /// Waits for the exit status and until the output of the app has been
+    /// forwarded, call before exiting.
+    pub fn finish(&mut self) -> Result<ExitStatus, Error> {
+        let status = self.wait()?;
+        self.observatory.join();
+        Ok(status)
+    }
+
+    /// Returns the exit status if the app has exited.
+    pub fn try_wait(&mut self) -> Result<Option<ExitStatus>, Error> {
+        if self.status.is_none() {
+            self.status = self.child.try_wait()?;
+        }
+        self.exited();
+        Ok(self.status)
+    }
+
+    pub fn wait(&mut self) -> Result<ExitStatus, Error> {
+        let status = match self.status {
+            Some(status) => status,
+            None => self.child.wait()?,
+        };
+        self.status = Some(status);
+        self.exited();
+        Ok(status)
+    }
+
+    pub fn kill(&mut self) -> Result<(), Error> {
+        if self.try_wait()?.is_none() {
+            self.child.kill()?;
+            self.wait()?;
+        }
+        Ok(())
+    }
+
+    fn exited(&self) {
+        if self.status.is_some() {
+            // The pid may be reused once the app has been reaped.
+            self.running.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Drop for AppProcess {
+    fn drop(&mut self) {
+        self.kill().ok();
+        #[cfg(unix)]
+        self.signals.close();
+    }
+}
+
+/// Runs the app with `cargo run` and waits for its observatory.
+pub struct Launcher<'a> {
+    cargo: &'a Cargo<'a>,
+    app: AppProcess,
+    uri: String,
+}
+
+impl<'a> Launcher<'a> {
+    pub fn launch(cargo: &'a Cargo<'a>) -> Result<Self, Error> {
+        // The observatory timeout can't exclude the build of a quiet cargo
+        if cargo.args().quiet {
+            cargo.exec_build()?;
+        }
+        let (app, uri) = start(cargo)?;
+        Ok(Self { cargo, app, uri })
+    }
+
+    /// Rebuilds and restarts the app. The running app is kept if the build
+    /// fails.
+    pub fn relaunch(&mut self) -> Result<(), Error> {
+        self.cargo.exec_build()?;
+        self.app.kill()?;
+        let (app, uri) = start(self.cargo)?;
+        self.app = app;
+        self.uri = uri;
+        Ok(())
+    }
+
+    /// Uri of the observatory, including its auth code.
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    pub fn app(&mut self) -> &mut AppProcess {
+        &mut self.app
+    }
+}
+
+fn start(cargo: &Cargo) -> Result<(AppProcess, String), Error> {
+    let mut app = AppProcess::new(cargo.spawn()?)?;
+    // The uri contains an auth code, it can't be derived from the port
+    let uri = if cargo.args().quiet {
+        // Cargo doesn't print when it starts the app, the build already ran
+        app.observatory().uri_within(OBSERVATORY_TIMEOUT)
+    } else {
+        app.observatory().uri(OBSERVATORY_TIMEOUT)
+    };
+    match uri {
+        Ok(uri) => Ok((app, uri)),
+        Err(Error::ObservatoryNotFound) => Err(Error::AppExited(exit_code(app.finish()?))),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(unix)]
+fn forward_signals(
+    pid: libc::pid_t,
+    running: Arc<AtomicBool>,
+) -> Result<signal_hook::iterator::Signals, Error> {
+    use signal_hook::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
+    let signals = signal_hook::iterator::Signals::new([SIGHUP, SIGINT, SIGQUIT, SIGTERM])?;
+    let handle = signals.clone();
+    // The handlers are unregistered once the thread and `handle` are gone
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            if running.load(Ordering::SeqCst) {
+                unsafe { libc::kill(pid, signal) };
+            }
+        }
+    });
+    Ok(handle)
+}
+
+/// Exit code of cargo-flutter for an app that exited with `status`, apps
+/// killed by a signal follow the shell convention of `128 + signal`.
+pub fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
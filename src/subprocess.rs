@@ -0,0 +1,70 @@
+use crate::error::Error;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, ExitStatus, Stdio};
+
+/// Number of stderr lines kept for `Error::SubprocessFailed`.
+const STDERR_TAIL: usize = 20;
+
+/// Runs `cmd` to completion. Its stderr is passed through and the last lines
+/// are kept for the error.
+pub fn run(step: &str, cmd: &mut Command) -> Result<(), Error> {
+    let mut child = cmd
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| spawn_failed(step, cmd, err))?;
+    let mut tail = VecDeque::with_capacity(STDERR_TAIL);
+    let mut stderr = std::io::stderr();
+    for line in BufReader::new(child.stderr.take().unwrap()).split(b'\n') {
+        let line = line?;
+        stderr.write_all(&line).ok();
+        stderr.write_all(b"\n").ok();
+        if tail.len() == STDERR_TAIL {
+            tail.pop_front();
+        }
+        tail.push_back(String::from_utf8_lossy(&line).into_owned());
+    }
+    let status = child.wait()?;
+    check(step, cmd, status, tail.into_iter().collect())
+}
+
+/// Runs `cmd` to completion with the terminal attached, for tools that
+/// show progress bars or colors.
+pub fn run_attached(step: &str, cmd: &mut Command) -> Result<(), Error> {
+    let status = cmd.status().map_err(|err| spawn_failed(step, cmd, err))?;
+    check(step, cmd, status, Vec::new())
+}
+
+pub fn check(
+    step: &str,
+    cmd: &Command,
+    status: ExitStatus,
+    stderr_tail: Vec<String>,
+) -> Result<(), Error> {
+    if status.success() {
+        return Ok(());
+    }
+    Err(failed(step, cmd, Some(status), stderr_tail))
+}
+
+pub fn spawn_failed(step: &str, cmd: &Command, err: std::io::Error) -> Error {
+    failed(step, cmd, None, vec![err.to_string()])
+}
+
+pub fn failed(
+    step: &str,
+    cmd: &Command,
+    status: Option<ExitStatus>,
+    stderr_tail: Vec<String>,
+) -> Error {
+    Error::SubprocessFailed {
+        step: step.to_string(),
+        program: cmd.get_program().to_string_lossy().into_owned(),
+        args: cmd
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect(),
+        status,
+        stderr_tail,
+    }
+}
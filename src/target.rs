@@ -0,0 +1,244 @@
+use crate::error::Error;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Names `gen_snapshot` is shipped under in the engine builds.
+const GEN_SNAPSHOT: &[&str] = &[
+    "gen_snapshot",
+    "gen_snapshot_x64",
+    "gen_snapshot_x86",
+    "gen_snapshot_host_targeting_host",
+    "gen_snapshot.exe",
+    // Layout of a local engine build
+    "clang_x64/gen_snapshot",
+    "clang_x86/gen_snapshot",
+];
+
+/// Describes how to fetch and package the engine for a target triple.
+///
+/// Additional targets can be declared in `Cargo.toml`:
+///
+/// ```toml
+/// [package.metadata.flutter.targets.x86_64-unknown-freebsd]
+/// platform = "freebsd_x64-host_{build}"
+/// library = "libflutter_engine.so"
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+pub struct TargetSpec {
+    #[serde(skip)]
+    triple: String,
+    /// Name of the engine asset, `{build}` is replaced with the engine build.
+    platform: String,
+    /// File name of the engine library.
+    library: String,
+    /// Files next to the engine library that need to be copied with it.
+    #[serde(default)]
+    sidecars: Vec<String>,
+    /// Candidate paths of `gen_snapshot` in the engine dir.
+    #[serde(default = "default_gen_snapshot")]
+    gen_snapshot: Vec<String>,
+    /// Supported packaging formats.
+    #[serde(default)]
+    formats: Vec<String>,
+    /// Whether the target is packaged as an android apk.
+    #[serde(default)]
+    android: bool,
+    /// Architecture name used by the packaging tools, for example `aarch64`.
+    arch: Option<String>,
+}
+
+fn default_gen_snapshot() -> Vec<String> {
+    GEN_SNAPSHOT.iter().map(|name| name.to_string()).collect()
+}
+
+impl TargetSpec {
+    fn new(triple: &str, platform: &str, library: &str) -> Self {
+        Self {
+            triple: triple.to_string(),
+            platform: platform.to_string(),
+            library: library.to_string(),
+            sidecars: Vec::new(),
+            gen_snapshot: default_gen_snapshot(),
+            formats: Vec::new(),
+            android: false,
+            arch: None,
+        }
+    }
+
+    fn gen_snapshot(mut self, gen_snapshot: &[&str]) -> Self {
+        self.gen_snapshot = gen_snapshot.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    fn arch(mut self, arch: &str) -> Self {
+        self.arch = Some(arch.to_string());
+        self
+    }
+
+    fn sidecars(mut self, sidecars: &[&str]) -> Self {
+        self.sidecars = sidecars.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    fn formats(mut self, formats: &[&str]) -> Self {
+        self.formats = formats.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    fn android(mut self) -> Self {
+        self.android = true;
+        self.formats(&["apk"])
+    }
+
+    pub fn triple(&self) -> &str {
+        &self.triple
+    }
+
+    pub fn platform(&self, build: &str) -> String {
+        self.platform.replace("{build}", build)
+    }
+
+    pub fn library_name(&self) -> &str {
+        &self.library
+    }
+
+    pub fn sidecar_names(&self) -> &[String] {
+        &self.sidecars
+    }
+
+    pub fn gen_snapshot_names(&self) -> &[String] {
+        &self.gen_snapshot
+    }
+
+    pub fn supports_format(&self, format: &str) -> bool {
+        self.formats.iter().any(|f| f == format)
+    }
+
+    pub fn is_android(&self) -> bool {
+        self.android
+    }
+
+    pub fn arch_name(&self) -> Option<&str> {
+        self.arch.as_deref()
+    }
+}
+
+/// Registry of the supported targets.
+#[derive(Clone, Debug)]
+pub struct Targets {
+    specs: Vec<TargetSpec>,
+}
+
+impl Targets {
+    pub fn builtin() -> Self {
+        let specs = vec![
+            TargetSpec::new(
+                "x86_64-unknown-linux-gnu",
+                "linux_x64-host_{build}",
+                "libflutter_engine.so",
+            )
+            .formats(&["appimage"])
+            .arch("x86_64"),
+            // Cross compiled from a x86_64 host, gen_snapshot runs on the host.
+            TargetSpec::new(
+                "aarch64-unknown-linux-gnu",
+                "linux_x64-linux_{build}_arm64",
+                "libflutter_engine.so",
+            )
+            .gen_snapshot(&["gen_snapshot", "clang_x64/gen_snapshot"])
+            .formats(&["appimage"])
+            .arch("aarch64"),
+            TargetSpec::new(
+                "armv7-unknown-linux-gnueabihf",
+                "linux_x64-linux_{build}_arm",
+                "libflutter_engine.so",
+            )
+            .gen_snapshot(&["gen_snapshot", "clang_x64/gen_snapshot"])
+            .formats(&["appimage"])
+            .arch("armhf"),
+            TargetSpec::new(
+                "armv7-linux-androideabi",
+                "linux_x64-android_{build}",
+                "libflutter_engine.so",
+            )
+            .android(),
+            TargetSpec::new(
+                "aarch64-linux-android",
+                "linux_x64-android_{build}_arm64",
+                "libflutter_engine.so",
+            )
+            .android(),
+            TargetSpec::new(
+                "i686-linux-android",
+                "linux_x64-android_{build}_x64",
+                "libflutter_engine.so",
+            )
+            .android(),
+            TargetSpec::new(
+                "x86_64-linux-android",
+                "linux_x64-android_{build}_x86",
+                "libflutter_engine.so",
+            )
+            .android(),
+            TargetSpec::new(
+                "x86_64-apple-darwin",
+                "macosx_x64-host_{build}",
+                "libflutter_engine.dylib",
+            )
+            .formats(&["dmg", "lipo"]),
+            TargetSpec::new(
+                "armv7-apple-ios",
+                "macosx_x64-ios_{build}_arm",
+                "libflutter_engine.dylib",
+            )
+            .formats(&["lipo"]),
+            TargetSpec::new(
+                "aarch64-apple-ios",
+                "macosx_x64-ios_{build}",
+                "libflutter_engine.dylib",
+            )
+            .formats(&["lipo"]),
+            TargetSpec::new(
+                "x86_64-pc-windows-msvc",
+                "windows_x64-host_{build}",
+                "flutter_engine.dll",
+            )
+            .sidecars(&[
+                "flutter_engine.lib",
+                "flutter_engine.exp",
+                "flutter_engine.pdb",
+            ])
+            .formats(&["nsis"]),
+        ];
+        Self { specs }
+    }
+
+    /// Adds or replaces targets declared in `[package.metadata.flutter.targets]`.
+    pub fn extend(&mut self, specs: HashMap<String, TargetSpec>) {
+        for (triple, mut spec) in specs {
+            spec.triple = triple;
+            self.specs.retain(|s| s.triple != spec.triple);
+            self.specs.push(spec);
+        }
+    }
+
+    pub fn get(&self, triple: &str) -> Result<&TargetSpec, Error> {
+        self.specs
+            .iter()
+            .find(|spec| spec.triple == triple)
+            .ok_or_else(|| Error::UnsupportedTarget {
+                target: triple.to_string(),
+                supported: self.triples(),
+            })
+    }
+
+    pub fn triples(&self) -> Vec<String> {
+        self.specs.iter().map(|spec| spec.triple.clone()).collect()
+    }
+}
+
+impl Default for Targets {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
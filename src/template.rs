@@ -0,0 +1,112 @@
+use crate::error::Error;
+use std::path::{Path, PathBuf};
+
+// `Cargo.toml.in` because cargo skips directories containing a manifest
+// when packaging.
+const CARGO_TOML: &str = include_str!("../templates/Cargo.toml.in");
+const PUBSPEC_YAML: &str = include_str!("../templates/pubspec.yaml");
+const GITIGNORE: &str = include_str!("../templates/gitignore");
+const ICON_SVG: &str = include_str!("../templates/icon.svg");
+const MAIN_RS: &str = include_str!("../templates/main.rs");
+const MAIN_DART: &str = include_str!("../templates/main.dart");
+const PLUGINS_MAIN_RS: &str = include_str!("../templates/main_plugins.rs");
+const PLUGINS_MAIN_DART: &str = include_str!("../templates/main_plugins.dart");
+const DRIVER_APP_DART: &str = include_str!("../templates/app.dart");
+const DRIVER_APP_TEST_DART: &str = include_str!("../templates/app_test.dart");
+
+/// Project templates of `cargo flutter new` and `init`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Template {
+    /// A counter app.
+    Minimal,
+    /// Uses a platform channel handled by a flutter-rs plugin.
+    Plugins,
+    /// The minimal app with a `flutter_driver` test.
+    Driver,
+}
+
+impl std::str::FromStr for Template {
+    type Err = Error;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        match template {
+            "minimal" => Ok(Self::Minimal),
+            "plugins" => Ok(Self::Plugins),
+            "driver" => Ok(Self::Driver),
+            _ => Err(Error::UnknownTemplate(template.to_string())),
+        }
+    }
+}
+
+impl Template {
+    /// Files of the project relative to its directory, `name` is the name of
+    /// the crate.
+    pub fn files(self, name: &str) -> Vec<(PathBuf, String)> {
+        let (main_rs, main_dart) = match self {
+            Self::Plugins => (PLUGINS_MAIN_RS, PLUGINS_MAIN_DART),
+            _ => (MAIN_RS, MAIN_DART),
+        };
+        let dependencies = match self {
+            Self::Plugins => {
+                "flutter-plugins = { git = \"https://github.com/flutter-rs/flutter-rs\" }\n"
+            }
+            _ => "",
+        };
+        let (flutter, dev_dependencies) = match self {
+            Self::Driver => (
+                "# Run the test with `cargo flutter --entrypoint driver --drive run`\n\
+                 entrypoints = { driver = \"test_driver/app.dart\" }\n",
+                "dev_dependencies:\n  flutter_driver:\n    sdk: flutter\n  test: any\n\n",
+            ),
+            _ => ("", ""),
+        };
+        let mut files = vec![
+            ("Cargo.toml", CARGO_TOML),
+            ("pubspec.yaml", PUBSPEC_YAML),
+            (".gitignore", GITIGNORE),
+            ("assets/icon.svg", ICON_SVG),
+            ("src/main.rs", main_rs),
+            ("lib/main.dart", main_dart),
+        ];
+        if self == Self::Driver {
+            files.push(("test_driver/app.dart", DRIVER_APP_DART));
+            files.push(("test_driver/app_test.dart", DRIVER_APP_TEST_DART));
+        }
+
+        // Dart package names can't contain dashes
+        let lib_name = name.replace('-', "_");
+        files
+            .into_iter()
+            .map(|(path, contents)| {
+                let contents = contents
+                    .replace("{{name}}", name)
+                    .replace("{{lib_name}}", &lib_name)
+                    .replace("{{dependencies}}", dependencies)
+                    .replace("{{flutter}}", flutter)
+                    .replace("{{dev_dependencies}}", dev_dependencies);
+                (PathBuf::from(path), contents)
+            })
+            .collect()
+    }
+
+    /// Writes the project to `dir`, which may exist but must not contain
+    /// any of its files.
+    pub fn generate(self, dir: &Path, name: &str) -> Result<(), Error> {
+        let valid = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid || !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return Err(Error::InvalidName(name.to_string()));
+        }
+        let files = self.files(name);
+        if let Some((path, _)) = files.iter().find(|(path, _)| dir.join(path).exists()) {
+            return Err(Error::FileExists(dir.join(path)));
+        }
+        for (path, contents) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,280 @@
+use crate::error::Error;
+use serde_json::{json, Value};
+use std::net::TcpStream;
+use tungstenite::{Message, WebSocket};
+
+/// Minimal client for the Dart VM service protocol, JSON-RPC 2.0 over a
+/// websocket.
+pub struct VmService {
+    socket: WebSocket<TcpStream>,
+    next_id: u64,
+}
+
+/// A flutter view and the isolate running its UI.
+#[derive(Clone, Debug)]
+pub struct FlutterView {
+    pub id: String,
+    pub isolate: Option<String>,
+}
+
+impl VmService {
+    /// Connects to the observatory at `uri`, for example
+    /// `http://127.0.0.1:36529/Nq0LD2bSBHQ=/`.
+    pub fn connect(uri: &str) -> Result<Self, Error> {
+        let (socket, _) = tungstenite::connect(ws_uri(uri))?;
+        Ok(Self { socket, next_id: 0 })
+    }
+
+    /// Calls `method` and waits for its result. Stream events and responses
+    /// to other requests are skipped.
+    pub fn call(&mut self, method: &str, params: Value) -> Result<Value, Error> {
+        self.next_id += 1;
+        let id = self.next_id.to_string();
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+        self.socket
+            .write_message(Message::Text(request.to_string()))?;
+        loop {
+            let text = match self.socket.read_message()? {
+                Message::Text(text) => text,
+                Message::Close(_) => return Err(tungstenite::Error::ConnectionClosed.into()),
+                _ => continue,
+            };
+            let mut response: Value = serde_json::from_str(&text)?;
+            if response["id"] != id.as_str() {
+                continue;
+            }
+            if let Some(error) = response.get("error") {
+                let message = error["data"]["details"]
+                    .as_str()
+                    .or_else(|| error["message"].as_str())
+                    .unwrap_or_default();
+                return Err(Error::VmService {
+                    method: method.to_string(),
+                    message: message.to_string(),
+                });
+            }
+            return Ok(response["result"].take());
+        }
+    }
+
+    pub fn list_views(&mut self) -> Result<Vec<FlutterView>, Error> {
+        let result = self.call("_flutter.listViews", json!({}))?;
+        let views = result["views"].as_array().cloned().unwrap_or_default();
+        Ok(views
+            .iter()
+            .filter_map(|view| {
+                Some(FlutterView {
+                    id: view["id"].as_str()?.to_string(),
+                    isolate: view["isolate"]["id"].as_str().map(|id| id.to_string()),
+                })
+            })
+            .collect())
+    }
+
+    /// Loads the libraries in the kernel file `dill` into the isolate.
+    pub fn reload_sources(&mut self, isolate: &str, dill: &str) -> Result<(), Error> {
+        let report = self.call(
+            "reloadSources",
+            json!({
+                "isolateId": isolate,
+                "pause": false,
+                "rootLibUri": dill,
+            }),
+        )?;
+        if report["success"].as_bool() != Some(false) {
+            return Ok(());
+        }
+        let notices = report["notices"].as_array().cloned().unwrap_or_default();
+        let message = notices
+            .iter()
+            .filter_map(|notice| notice["message"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        Err(Error::ReloadRejected(message))
+    }
+
+    /// Rebuilds the widget tree so it picks up the reloaded code.
+    pub fn reassemble(&mut self, isolate: &str) -> Result<(), Error> {
+        self.call("ext.flutter.reassemble", json!({ "isolateId": isolate }))?;
+        Ok(())
+    }
+
+    /// Replaces the isolate of a view with a new one running `main_script`.
+    pub fn run_in_view(
+        &mut self,
+        view: &str,
+        main_script: &str,
+        asset_directory: &str,
+    ) -> Result<(), Error> {
+        self.call(
+            "_flutter.runInView",
+            json!({
+                "viewId": view,
+                "mainScript": main_script,
+                "assetDirectory": asset_directory,
+            }),
+        )?;
+        Ok(())
+    }
+}
+
+/// Converts the http uri printed by the observatory to the uri of its
+/// websocket.
+pub fn ws_uri(uri: &str) -> String {
+    let uri = uri.trim().trim_end_matches('/');
+    let uri = if let Some(rest) = uri.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = uri.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        uri.to_string()
+    };
+    if uri.ends_with("/ws") {
+        uri
+    } else {
+        format!("{}/ws", uri)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    /// Serves one connection with `handler` and returns the uri printed by
+    /// the observatory.
+    fn fake_vm<F>(handler: F) -> (String, JoinHandle<()>)
+    where
+        F: FnOnce(&mut WebSocket<TcpStream>) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            handler(&mut socket);
+        });
+        (format!("http://127.0.0.1:{}/Nq0LD2bSBHQ=/", port), server)
+    }
+
+    fn read_request(socket: &mut WebSocket<TcpStream>) -> Value {
+        match socket.read_message().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            message => panic!("unexpected message {:?}", message),
+        }
+    }
+
+    fn send(socket: &mut WebSocket<TcpStream>, message: Value) {
+        socket
+            .write_message(Message::Text(message.to_string()))
+            .unwrap();
+    }
+
+    #[test]
+    fn call_skips_events_and_other_responses() {
+        let (uri, server) = fake_vm(|socket| {
+            let request = read_request(socket);
+            assert_eq!(request["method"], "getVM");
+            send(
+                socket,
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": "streamNotify",
+                    "params": { "streamId": "Stdout" },
+                }),
+            );
+            send(
+                socket,
+                json!({ "jsonrpc": "2.0", "id": "other", "result": 1 }),
+            );
+            send(
+                socket,
+                json!({ "jsonrpc": "2.0", "id": request["id"], "result": { "type": "VM" } }),
+            );
+        });
+        let mut vm = VmService::connect(&uri).unwrap();
+        let result = vm.call("getVM", json!({})).unwrap();
+        assert_eq!(result, json!({ "type": "VM" }));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn call_returns_error_details() {
+        let (uri, server) = fake_vm(|socket| {
+            let request = read_request(socket);
+            send(
+                socket,
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "error": {
+                        "code": 113,
+                        "message": "Server error",
+                        "data": { "details": "isolate is gone" },
+                    },
+                }),
+            );
+        });
+        let mut vm = VmService::connect(&uri).unwrap();
+        match vm.call("resume", json!({})) {
+            Err(Error::VmService { method, message }) => {
+                assert_eq!(method, "resume");
+                assert_eq!(message, "isolate is gone");
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn reload_sources_rejected() {
+        let (uri, server) = fake_vm(|socket| {
+            let request = read_request(socket);
+            assert_eq!(request["method"], "reloadSources");
+            assert_eq!(request["params"]["isolateId"], "isolates/1");
+            send(
+                socket,
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "result": {
+                        "type": "ReloadReport",
+                        "success": false,
+                        "notices": [
+                            { "type": "ReasonForCancelling", "message": "first" },
+                            { "type": "ReasonForCancelling", "message": "second" },
+                        ],
+                    },
+                }),
+            );
+        });
+        let mut vm = VmService::connect(&uri).unwrap();
+        match vm.reload_sources("isolates/1", "file:///app.dill") {
+            Err(Error::ReloadRejected(reason)) => assert_eq!(reason, "first\nsecond"),
+            result => panic!("unexpected result {:?}", result),
+        }
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn ws_uri_of_observatory() {
+        assert_eq!(
+            ws_uri("http://127.0.0.1:36529/Nq0LD2bSBHQ=/"),
+            "ws://127.0.0.1:36529/Nq0LD2bSBHQ=/ws"
+        );
+        assert_eq!(
+            ws_uri("https://127.0.0.1:36529/Nq0LD2bSBHQ=/\n"),
+            "wss://127.0.0.1:36529/Nq0LD2bSBHQ=/ws"
+        );
+        assert_eq!(
+            ws_uri("ws://127.0.0.1:36529/Nq0LD2bSBHQ=/ws"),
+            "ws://127.0.0.1:36529/Nq0LD2bSBHQ=/ws"
+        );
+    }
+}
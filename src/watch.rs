@@ -0,0 +1,92 @@
+use crate::cargo::Cargo;
+use crate::error::Error;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::io::BufRead;
+use std::path::Path;
+use std::sync::mpsc::{channel, Sender};
+use std::time::Duration;
+
+/// Requests to an attached session, ordered by precedence so a burst of
+/// them can be handled at once.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Action {
+    Reload,
+    Restart,
+    Relaunch,
+    Quit,
+}
+
+/// Sends the actions typed on stdin: `r` to hot reload, `R` to hot restart
+/// and `q` to quit.
+pub fn read_stdin(actions: Sender<Action>) {
+    std::thread::spawn(move || {
+        let stdin = std::io::stdin();
+        for line in stdin.lock().lines() {
+            let action = match line.as_ref().map(|line| line.trim()) {
+                Ok("r") => Action::Reload,
+                Ok("R") => Action::Restart,
+                Ok("q") | Err(_) => Action::Quit,
+                Ok(_) => continue,
+            };
+            if actions.send(action).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Watches the dart sources in `root/lib` and the rust sources of the
+/// workspace members. Dart changes are hot reloaded, rust changes relaunch
+/// the app.
+///
+/// The sources are watched as long as the returned watcher is alive.
+pub fn watch_sources(
+    cargo: &Cargo,
+    root: &Path,
+    actions: Sender<Action>,
+) -> Result<RecommendedWatcher, Error> {
+    let (sender, events) = channel();
+    let mut watcher = notify::watcher(sender, Duration::from_millis(200))?;
+    let lib_dir = root.join("lib");
+    if lib_dir.is_dir() {
+        watcher.watch(&lib_dir, RecursiveMode::Recursive)?;
+    }
+    for member in cargo.workspace().members() {
+        let src_dir = member.root().join("src");
+        if src_dir.is_dir() {
+            watcher.watch(&src_dir, RecursiveMode::Recursive)?;
+        }
+        watcher.watch(member.manifest_path(), RecursiveMode::NonRecursive)?;
+    }
+
+    std::thread::spawn(move || {
+        for event in events {
+            let path = match &event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Remove(path)
+                | DebouncedEvent::Rename(_, path) => path,
+                _ => continue,
+            };
+            let action = match action(path) {
+                Some(action) => action,
+                None => continue,
+            };
+            if actions.send(action).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(watcher)
+}
+
+fn action(path: &Path) -> Option<Action> {
+    if path.file_name().and_then(|name| name.to_str()) == Some("Cargo.toml") {
+        return Some(Action::Relaunch);
+    }
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("dart") => Some(Action::Reload),
+        Some("rs") => Some(Action::Relaunch),
+        _ => None,
+    }
+}
@@ -0,0 +1,7 @@
+import 'package:flutter_driver/driver_extension.dart';
+import 'package:{{lib_name}}/main.dart' as app;
+
+void main() {
+  enableFlutterDriverExtension();
+  app.main();
+}
@@ -0,0 +1,25 @@
+import 'package:flutter_driver/flutter_driver.dart';
+import 'package:test/test.dart';
+
+void main() {
+  group('{{name}}', () {
+    FlutterDriver driver;
+
+    setUpAll(() async {
+      driver = await FlutterDriver.connect();
+    });
+
+    tearDownAll(() async {
+      if (driver != null) {
+        await driver.close();
+      }
+    });
+
+    test('increments the counter', () async {
+      final counter = find.byValueKey('counter');
+      expect(await driver.getText(counter), '0');
+      await driver.tap(find.byValueKey('increment'));
+      expect(await driver.getText(counter), '1');
+    });
+  });
+}
@@ -0,0 +1,43 @@
+import 'package:flutter/material.dart';
+
+void main() => runApp(App());
+
+class App extends StatelessWidget {
+  @override
+  Widget build(BuildContext context) {
+    return MaterialApp(
+      title: '{{name}}',
+      theme: ThemeData(primarySwatch: Colors.blue),
+      home: HomePage(),
+    );
+  }
+}
+
+class HomePage extends StatefulWidget {
+  @override
+  _HomePageState createState() => _HomePageState();
+}
+
+class _HomePageState extends State<HomePage> {
+  int _counter = 0;
+
+  @override
+  Widget build(BuildContext context) {
+    return Scaffold(
+      appBar: AppBar(title: Text('{{name}}')),
+      body: Center(
+        child: Text(
+          '$_counter',
+          key: Key('counter'),
+          style: Theme.of(context).textTheme.display1,
+        ),
+      ),
+      floatingActionButton: FloatingActionButton(
+        key: Key('increment'),
+        onPressed: () => setState(() => _counter++),
+        tooltip: 'Increment',
+        child: Icon(Icons.add),
+      ),
+    );
+  }
+}
@@ -0,0 +1,49 @@
+import 'package:flutter/material.dart';
+import 'package:flutter/services.dart';
+
+void main() => runApp(App());
+
+class App extends StatelessWidget {
+  @override
+  Widget build(BuildContext context) {
+    return MaterialApp(
+      title: '{{name}}',
+      theme: ThemeData(primarySwatch: Colors.blue),
+      home: HomePage(),
+    );
+  }
+}
+
+class HomePage extends StatefulWidget {
+  @override
+  _HomePageState createState() => _HomePageState();
+}
+
+class _HomePageState extends State<HomePage> {
+  // Handled by the `DialogPlugin` registered in `src/main.rs`
+  static const _dialog = MethodChannel('flutter-rs/dialog', JSONMethodCodec());
+
+  String _file = 'No file selected';
+
+  Future<void> _openFile() async {
+    final file = await _dialog.invokeMethod('open_file_dialog', {
+      'title': 'Open file',
+      'path': '',
+      'filter': [[], 'All files'],
+    });
+    setState(() => _file = file ?? 'No file selected');
+  }
+
+  @override
+  Widget build(BuildContext context) {
+    return Scaffold(
+      appBar: AppBar(title: Text('{{name}}')),
+      body: Center(child: Text(_file)),
+      floatingActionButton: FloatingActionButton(
+        onPressed: _openFile,
+        tooltip: 'Open file',
+        child: Icon(Icons.folder_open),
+      ),
+    );
+  }
+}